@@ -1,6 +1,8 @@
 extern crate gtk;
 
-use rustgtk::modules::home_screen::{HomeScreen, SharedData};
+use rustgtk::modules::home_screen::{HomeScreen, SharedData, HOME_SCREEN, KEYBOARD_SCREEN};
+use rustgtk::modules::keyboard_layout::InputPurpose;
+use rustgtk::modules::theme;
 use rustgtk::modules::virtual_keyboard;
 
 use crate::virtual_keyboard::{VirtualKeyboard, SCREEN_HEIGHT, SCREEN_WIDTH};
@@ -8,11 +10,26 @@ use crate::virtual_keyboard::{VirtualKeyboard, SCREEN_HEIGHT, SCREEN_WIDTH};
 use gtk::prelude::*;
 use gtk::{Window, WindowType};
 
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::rc::Rc;
 
 fn main() {
     gtk::init().expect("Failed to initialize GTK.");
+    // RUSTGTK_THEME=dark picks a theme; a .css file is loaded and reloaded
+    // every time it is saved, for working on a theme
+    let themes = theme::themes();
+    if let Ok(wanted) = std::env::var("RUSTGTK_THEME") {
+        let selected = if wanted.ends_with(".css") {
+            themes.set_hot_reload(true);
+            themes
+                .add_file(&wanted)
+                .and_then(|name| themes.set_theme(&name))
+        } else {
+            themes.set_theme(&wanted)
+        };
+        if let Err(error) = selected {
+            eprintln!("{}", error);
+        }
+    }
     // define the window
     let window = Window::new(WindowType::Toplevel);
     window.set_title("Hello, World!");
@@ -28,23 +45,31 @@ fn main() {
     let style_context = vbox_main.style_context();
     style_context.add_class("root");
 
-    let shared_data = Arc::new(Mutex::new(SharedData::new()));
+    let shared_data = Rc::new(SharedData::new());
+
+    // Screens are pages of the navigator's stack
+    let navigator = shared_data.navigator.clone();
+    vbox_main.pack_start(&navigator.widget(), true, true, 0);
 
     // Construct home screen
-    let home_screen = HomeScreen::new(Arc::clone(&shared_data));
-    vbox_main.pack_start(&home_screen.widget, true, true, 0);
-    shared_data.lock().expect("poison").home_screen = Mutex::new(Some(home_screen));
+    let home_screen = HomeScreen::new(Rc::clone(&shared_data));
+    navigator.register(HOME_SCREEN, home_screen);
 
     // Construct virtual keyboard instance
     let virtual_keyboard = VirtualKeyboard::new(
-        &window,
-        Arc::clone(&shared_data),
         "Please enter some text.",
         "", // empty=allow all chars (otherwise only allow listed chars)
+        InputPurpose::FreeForm,
     );
-    vbox_main.pack_start(&virtual_keyboard.widget, true, true, 0);
-    shared_data.lock().expect("poison").virtual_keyboard = Some(virtual_keyboard);
+    navigator.register(KEYBOARD_SCREEN, virtual_keyboard.clone());
+
+    // Let a physical keyboard type into the virtual keyboard as well
+    window.connect_local("key_press_event", false, move |values| {
+        virtual_keyboard::physical_keyboard_handler(&virtual_keyboard, values)
+    });
 
+    navigator.push(HOME_SCREEN).expect("home screen registered");
+    navigator.widget().show();
     vbox_main.show();
     window.show();
 
@@ -0,0 +1,316 @@
+// What assistive technology says about the keyboard.
+//
+// Special keys are labelled with glyphs (⌫, ⇧, ◁, ✔, ...) that a screen
+// reader can't make anything of, so every key gets a spoken name and a
+// description of what it does. Pressing a key is answered with an
+// announcement of what it did: the character typed or deleted, where the
+// cursor went, or the layer now showing. The wording is all here; the
+// virtual keyboard hands it to ATK.
+use crate::modules::modifier_state::ShiftState;
+use crate::modules::text_model::{self, TextModel};
+use crate::modules::virtual_keyboard::{
+    ID_BACKSPACE, ID_CANCEL, ID_CLEAR, ID_COMPOSE, ID_COPY, ID_CUT, ID_DEAD, ID_DELETE,
+    ID_DELETE_WORD_BACK, ID_DELETE_WORD_FORWARD, ID_END, ID_ENTER, ID_HOME, ID_INSERT,
+    ID_LAYER_PREFIX, ID_LAYOUT, ID_LEFT, ID_PASTE, ID_REDO, ID_RIGHT, ID_SELECT_ALL, ID_SELECT_END,
+    ID_SELECT_HOME, ID_SELECT_LEFT, ID_SELECT_RIGHT, ID_SHIFT, ID_SYMBOLS, ID_UNDO, ID_WORD_LEFT,
+    ID_WORD_RIGHT,
+};
+
+// A character or piece of text, the way it is read out.
+pub fn spoken(text: &str) -> String {
+    match text {
+        " " => "space".to_string(),
+        "\t" => "tab".to_string(),
+        _ => text.to_string(),
+    }
+}
+
+fn accent_name(accent: &str) -> &str {
+    match accent {
+        "´" => "acute",
+        "`" => "grave",
+        "^" => "circumflex",
+        "¨" => "diaeresis",
+        "~" => "tilde",
+        "¸" => "cedilla",
+        "˚" => "ring",
+        "ˇ" => "caron",
+        _ => accent,
+    }
+}
+
+// The accessible name of a key with special id `id` ("" for character
+// keys) and the label it shows.
+pub fn key_name(id: &str, label: &str) -> String {
+    if let Some(layer) = id.strip_prefix(ID_LAYER_PREFIX) {
+        return format!("Layer {}", layer);
+    }
+    let name = match id {
+        ID_ENTER => "OK",
+        ID_CANCEL => "Cancel",
+        ID_BACKSPACE => "Backspace",
+        ID_LEFT => "Left",
+        ID_RIGHT => "Right",
+        ID_INSERT => "Insert",
+        ID_DELETE => "Delete",
+        ID_HOME => "Home",
+        ID_END => "End",
+        ID_WORD_LEFT => "Word left",
+        ID_WORD_RIGHT => "Word right",
+        ID_DELETE_WORD_BACK => "Delete word back",
+        ID_DELETE_WORD_FORWARD => "Delete word forward",
+        ID_CLEAR => "Clear",
+        ID_SELECT_LEFT => "Select left",
+        ID_SELECT_RIGHT => "Select right",
+        ID_SELECT_HOME => "Select to start",
+        ID_SELECT_END => "Select to end",
+        ID_SELECT_ALL => "Select all",
+        ID_COPY => "Copy",
+        ID_CUT => "Cut",
+        ID_PASTE => "Paste",
+        ID_UNDO => "Undo",
+        ID_REDO => "Redo",
+        ID_SHIFT => "Shift",
+        // labelled "abc" on the symbols layer, to go back
+        ID_SYMBOLS if label.chars().all(char::is_alphabetic) => "Letters",
+        ID_SYMBOLS => "Symbols",
+        ID_COMPOSE => "Compose",
+        ID_LAYOUT => "Next layout",
+        ID_DEAD => return format!("Dead {}", accent_name(label)),
+        _ => return spoken(label),
+    };
+    name.to_string()
+}
+
+// What the key does; "" for a plain character key.
+pub fn key_description(id: &str, alternates: &[String]) -> String {
+    if let Some(layer) = id.strip_prefix(ID_LAYER_PREFIX) {
+        return format!("Switches to the {} layer and back", layer);
+    }
+    let description = match id {
+        ID_ENTER => "Accepts the text",
+        ID_CANCEL => "Closes the keyboard without the text",
+        ID_BACKSPACE => "Deletes the character before the cursor",
+        ID_LEFT => "Moves the cursor one character left",
+        ID_RIGHT => "Moves the cursor one character right",
+        ID_INSERT => "Switches between inserting and overwriting",
+        ID_DELETE => "Deletes the character at the cursor",
+        ID_HOME => "Moves the cursor to the start",
+        ID_END => "Moves the cursor to the end",
+        ID_WORD_LEFT => "Moves the cursor one word left",
+        ID_WORD_RIGHT => "Moves the cursor one word right",
+        ID_DELETE_WORD_BACK => "Deletes the word before the cursor",
+        ID_DELETE_WORD_FORWARD => "Deletes the word after the cursor",
+        ID_CLEAR => "Deletes all text",
+        ID_SELECT_LEFT => "Extends the selection one character left",
+        ID_SELECT_RIGHT => "Extends the selection one character right",
+        ID_SELECT_HOME => "Extends the selection to the start",
+        ID_SELECT_END => "Extends the selection to the end",
+        ID_SELECT_ALL => "Selects all text",
+        ID_COPY => "Copies the selection",
+        ID_CUT => "Cuts the selection",
+        ID_PASTE => "Pastes at the cursor",
+        ID_UNDO => "Undoes the last edit",
+        ID_REDO => "Redoes the last undone edit",
+        ID_SHIFT => "Capitals for one letter; press twice for caps lock",
+        ID_SYMBOLS => "Switches between letters and symbols",
+        ID_COMPOSE => "Starts a compose sequence",
+        ID_LAYOUT => "Switches to the next keyboard layout",
+        ID_DEAD => "Accents the next letter",
+        _ if !alternates.is_empty() => {
+            let alternates: Vec<String> = alternates.iter().map(|a| spoken(a)).collect();
+            return format!("Hold for {}", alternates.join(" "));
+        }
+        _ => "",
+    };
+    description.to_string()
+}
+
+// Where the cursor is, for the description of the input.
+pub fn cursor_description(model: &TextModel) -> String {
+    format!("Cursor at {} of {}", model.cursor(), model.len())
+}
+
+// The layer showing after a press of a modifier key; `layer` is a named
+// layer that has its own key.
+pub fn layer_announcement(shift: ShiftState, symbols: bool, layer: Option<&str>) -> String {
+    if let Some(layer) = layer {
+        return format!("Layer {}", layer);
+    }
+    let layer = if symbols {
+        "Symbols"
+    } else {
+        match shift {
+            ShiftState::Off => "Lower case",
+            ShiftState::OneShot => "Shift",
+            ShiftState::Locked => "Caps lock",
+        }
+    };
+    layer.to_string()
+}
+
+pub fn layout_announcement(layout_name: &str) -> String {
+    format!("Layout {}", layout_name)
+}
+
+// What an edit did to the text, or None if it did nothing to say. Masked
+// input (a PIN) is never read out.
+pub fn edit_announcement(before: &TextModel, after: &TextModel, masked: bool) -> Option<String> {
+    let (old, new) = (before.text(), after.text());
+    if old != new {
+        // the part that changed is between a common start and end
+        let old_len = text_model::grapheme_count(old);
+        let new_len = text_model::grapheme_count(new);
+        let mut start = 0;
+        while start < old_len.min(new_len)
+            && text_model::slice(old, start, start + 1) == text_model::slice(new, start, start + 1)
+        {
+            start += 1;
+        }
+        let mut end = 0;
+        while end < old_len.min(new_len) - start
+            && text_model::slice(old, old_len - end - 1, old_len - end)
+                == text_model::slice(new, new_len - end - 1, new_len - end)
+        {
+            end += 1;
+        }
+        let removed = text_model::slice(old, start, old_len - end);
+        let inserted = text_model::slice(new, start, new_len - end);
+        let announcement = match (removed.is_empty(), inserted.is_empty(), masked) {
+            (true, _, true) => "Character typed".to_string(),
+            (_, true, true) => "Character deleted".to_string(),
+            (_, _, true) => "Character replaced".to_string(),
+            (true, _, false) => spoken(inserted),
+            (_, true, false) => format!("Deleted {}", spoken(removed)),
+            _ => format!("{} replaces {}", spoken(inserted), spoken(removed)),
+        };
+        return Some(announcement);
+    }
+    if before.insert_mode() != after.insert_mode() {
+        let mode = if after.insert_mode() {
+            "Insert mode"
+        } else {
+            "Overwrite mode"
+        };
+        return Some(mode.to_string());
+    }
+    if before.selection() != after.selection() {
+        return Some(match (after.selected_text(), masked) {
+            (Some(selected), false) => format!("Selected {}", spoken(selected)),
+            (Some(selected), true) => {
+                format!(
+                    "Selected {} characters",
+                    text_model::grapheme_count(selected)
+                )
+            }
+            (None, _) => "Selection cleared".to_string(),
+        });
+    }
+    if before.cursor() != after.cursor() {
+        return Some(match (after.on_cursor(), masked) {
+            (None, _) => "End of text".to_string(),
+            (Some(_), true) => cursor_description(after),
+            (Some(character), false) => spoken(character),
+        });
+    }
+    None
+}
+
+#[test]
+fn test_key_names() {
+    assert_eq!(key_name(ID_BACKSPACE, "⌫"), "Backspace");
+    assert_eq!(key_name(ID_ENTER, "✔"), "OK");
+    assert_eq!(key_name(ID_SYMBOLS, "?123"), "Symbols");
+    assert_eq!(key_name(ID_SYMBOLS, "abc"), "Letters");
+    assert_eq!(key_name(ID_DEAD, "´"), "Dead acute");
+    assert_eq!(key_name("", " "), "space");
+    assert_eq!(key_name("", "é"), "é");
+    assert_eq!(key_description("", &[]), "");
+    assert_eq!(
+        key_description("", &["é".to_string(), "è".to_string()]),
+        "Hold for é è"
+    );
+    assert_eq!(
+        key_description(ID_BACKSPACE, &[]),
+        "Deletes the character before the cursor"
+    );
+    assert_eq!(key_name("layer:alt_gr", "AltGr"), "Layer alt_gr");
+    assert_eq!(
+        layer_announcement(ShiftState::Locked, false, None),
+        "Caps lock"
+    );
+    assert_eq!(
+        layer_announcement(ShiftState::OneShot, true, None),
+        "Symbols"
+    );
+    assert_eq!(
+        layer_announcement(ShiftState::Off, false, Some("extra")),
+        "Layer extra"
+    );
+}
+
+#[test]
+fn test_edit_announcements() {
+    let model = |text: &str, cursor: usize| {
+        let mut model = TextModel::new();
+        model.set_text(text, cursor);
+        model
+    };
+    let announce = |before: &TextModel, after: &TextModel| edit_announcement(before, after, false);
+    assert_eq!(
+        announce(&model("ab", 2), &model("abc", 3)).as_deref(),
+        Some("c")
+    );
+    assert_eq!(
+        announce(&model("ab", 2), &model("ab ", 3)).as_deref(),
+        Some("space")
+    );
+    assert_eq!(
+        announce(&model("abc", 2), &model("ac", 1)).as_deref(),
+        Some("Deleted b")
+    );
+    // the same letter twice in a row is still one deletion
+    assert_eq!(
+        announce(&model("aab", 2), &model("ab", 1)).as_deref(),
+        Some("Deleted a")
+    );
+    assert_eq!(
+        announce(&model("abc", 1), &model("axc", 2)).as_deref(),
+        Some("x replaces b")
+    );
+    assert_eq!(
+        announce(&model("abc", 3), &model("abc", 1)).as_deref(),
+        Some("b")
+    );
+    assert_eq!(
+        announce(&model("abc", 1), &model("abc", 3)).as_deref(),
+        Some("End of text")
+    );
+    assert_eq!(announce(&model("abc", 1), &model("abc", 1)), None);
+    let mut selected = model("abc", 3);
+    selected.select_all();
+    assert_eq!(
+        announce(&model("abc", 3), &selected).as_deref(),
+        Some("Selected abc")
+    );
+    let mut inserting = model("abc", 3);
+    inserting.toggle_insert_mode();
+    assert_eq!(
+        announce(&model("abc", 3), &inserting).as_deref(),
+        Some(if inserting.insert_mode() {
+            "Insert mode"
+        } else {
+            "Overwrite mode"
+        })
+    );
+
+    // a PIN stays secret
+    assert_eq!(
+        edit_announcement(&model("12", 2), &model("123", 3), true).as_deref(),
+        Some("Character typed")
+    );
+    assert_eq!(
+        edit_announcement(&model("123", 3), &model("123", 1), true).as_deref(),
+        Some("Cursor at 1 of 3")
+    );
+}
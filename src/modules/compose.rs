@@ -0,0 +1,269 @@
+// Dead keys and compose sequences.
+//
+// A dead key (e.g. ´) doesn't type anything by itself but modifies the next
+// character (´ then e gives é). The compose key starts a two-character
+// sequence (compose, o, / gives ø). Both the on-screen keys and the physical
+// keyboard feed the same Composer, so they behave identically.
+
+// Dead key accents (as their spacing characters) with the base characters
+// they combine with, as pairs of (base, result).
+const DEAD_KEYS: &[(char, &str)] = &[
+    ('`', "aàeèiìoòuùAÀEÈIÌOÒUÙ"),
+    ('´', "aáeéiíoóuúyýcćnńsśzźAÁEÉIÍOÓUÚYÝCĆNŃSŚZŹ"),
+    ('^', "aâeêiîoôuûAÂEÊIÎOÔUÛ"),
+    ('¨', "aäeëiïoöuüyÿAÄEËIÏOÖUÜYŸ"),
+    ('~', "aãnñoõAÃNÑOÕ"),
+    ('¸', "cçCÇ"),
+    ('˚', "aåuůAÅUŮ"),
+    ('ˇ', "cčsšzžrřeěnňCČSŠZŽRŘEĚNŇ"),
+];
+
+// ASCII stand-ins for the accents, for use in compose sequences
+// (compose, ", u gives ü).
+const ACCENT_ALIASES: &[(char, char)] =
+    &[('\'', '´'), ('"', '¨'), (',', '¸'), ('o', '˚'), ('<', 'ˇ')];
+
+// Compose sequences that aren't accented letters. Either order is accepted.
+const COMPOSE_SEQUENCES: &[(&str, &str)] = &[
+    ("ss", "ß"),
+    ("ae", "æ"),
+    ("AE", "Æ"),
+    ("oe", "œ"),
+    ("OE", "Œ"),
+    ("o/", "ø"),
+    ("O/", "Ø"),
+    ("e=", "€"),
+    ("L-", "£"),
+    ("Y=", "¥"),
+    ("c/", "¢"),
+    ("co", "©"),
+    ("ro", "®"),
+    ("tm", "™"),
+    ("!!", "¡"),
+    ("??", "¿"),
+    ("<<", "«"),
+    (">>", "»"),
+    ("12", "½"),
+    ("14", "¼"),
+    ("34", "¾"),
+    ("+-", "±"),
+    ("xx", "×"),
+    (":-", "÷"),
+    ("^2", "²"),
+    ("^3", "³"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposeInput<'a> {
+    // a dead key, given as the spacing form of its accent
+    Dead(char),
+    // the compose key
+    Compose,
+    // ordinary text from a key
+    Text(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposeOutput {
+    // the input was swallowed, waiting for more
+    Pending,
+    // text to insert
+    Commit(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pending {
+    Dead(char),
+    // compose key pressed, with the characters typed since
+    Sequence(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Composer {
+    pending: Option<Pending>,
+}
+
+impl Composer {
+    pub fn new() -> Composer {
+        Composer { pending: None }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    // forget any unfinished dead key or compose sequence
+    pub fn reset(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn feed(&mut self, input: ComposeInput) -> ComposeOutput {
+        match (self.pending.take(), input) {
+            (None, ComposeInput::Text(text)) => ComposeOutput::Commit(text.to_string()),
+            (None, ComposeInput::Dead(accent)) => {
+                self.pending = Some(Pending::Dead(accent));
+                ComposeOutput::Pending
+            }
+            (_, ComposeInput::Compose) => {
+                self.pending = Some(Pending::Sequence(String::new()));
+                ComposeOutput::Pending
+            }
+            (Some(Pending::Dead(accent)), ComposeInput::Dead(second)) => {
+                if accent == second {
+                    // pressing a dead key twice types the accent itself
+                    ComposeOutput::Commit(accent.to_string())
+                } else {
+                    self.pending = Some(Pending::Dead(second));
+                    ComposeOutput::Commit(accent.to_string())
+                }
+            }
+            (Some(Pending::Dead(accent)), ComposeInput::Text(text)) => {
+                ComposeOutput::Commit(Self::apply_dead_key(accent, text))
+            }
+            (Some(Pending::Sequence(sequence)), ComposeInput::Dead(accent)) => {
+                self.continue_sequence(sequence, accent)
+            }
+            (Some(Pending::Sequence(sequence)), ComposeInput::Text(text)) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => self.continue_sequence(sequence, c),
+                    // not something we can compose with; give up
+                    _ => ComposeOutput::Commit(format!("{}{}", sequence, text)),
+                }
+            }
+        }
+    }
+
+    fn continue_sequence(&mut self, mut sequence: String, c: char) -> ComposeOutput {
+        sequence.push(c);
+        if sequence.chars().count() < 2 {
+            self.pending = Some(Pending::Sequence(sequence));
+            return ComposeOutput::Pending;
+        }
+        match Self::lookup_sequence(&sequence) {
+            Some(result) => ComposeOutput::Commit(result),
+            None => ComposeOutput::Commit(sequence),
+        }
+    }
+
+    fn lookup_sequence(sequence: &str) -> Option<String> {
+        let chars: Vec<char> = sequence.chars().collect();
+        let reversed: String = chars.iter().rev().collect();
+        for candidate in [sequence, reversed.as_str()] {
+            if let Some((_, result)) = COMPOSE_SEQUENCES.iter().find(|(s, _)| *s == candidate) {
+                return Some(result.to_string());
+            }
+        }
+        // an accent (or its ASCII stand-in) together with a letter
+        for (first, second) in [(chars[0], chars[1]), (chars[1], chars[0])] {
+            let accent = ACCENT_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == first)
+                .map(|(_, accent)| *accent)
+                .unwrap_or(first);
+            if let Some(result) = Self::combine(accent, second) {
+                return Some(result.to_string());
+            }
+        }
+        None
+    }
+
+    fn combine(accent: char, base: char) -> Option<char> {
+        let (_, pairs) = DEAD_KEYS.iter().find(|(a, _)| *a == accent)?;
+        let pairs: Vec<char> = pairs.chars().collect();
+        pairs
+            .chunks(2)
+            .find(|pair| pair[0] == base)
+            .map(|pair| pair[1])
+    }
+
+    fn apply_dead_key(accent: char, text: &str) -> String {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            // dead key then space types the accent itself
+            (Some(' '), None) => accent.to_string(),
+            (Some(base), None) => match Self::combine(accent, base) {
+                Some(combined) => combined.to_string(),
+                None => format!("{}{}", accent, base),
+            },
+            _ => format!("{}{}", accent, text),
+        }
+    }
+}
+
+#[test]
+fn test_dead_keys() {
+    let mut composer = Composer::new();
+    assert_eq!(
+        composer.feed(ComposeInput::Dead('´')),
+        ComposeOutput::Pending
+    );
+    assert!(composer.is_pending());
+    assert_eq!(
+        composer.feed(ComposeInput::Text("e")),
+        ComposeOutput::Commit("é".to_string())
+    );
+    assert!(!composer.is_pending());
+    composer.feed(ComposeInput::Dead('¨'));
+    assert_eq!(
+        composer.feed(ComposeInput::Text("U")),
+        ComposeOutput::Commit("Ü".to_string())
+    );
+    // no such letter: type both
+    composer.feed(ComposeInput::Dead('^'));
+    assert_eq!(
+        composer.feed(ComposeInput::Text("q")),
+        ComposeOutput::Commit("^q".to_string())
+    );
+    // space or a second press types the accent
+    composer.feed(ComposeInput::Dead('`'));
+    assert_eq!(
+        composer.feed(ComposeInput::Text(" ")),
+        ComposeOutput::Commit("`".to_string())
+    );
+    composer.feed(ComposeInput::Dead('´'));
+    assert_eq!(
+        composer.feed(ComposeInput::Dead('´')),
+        ComposeOutput::Commit("´".to_string())
+    );
+    assert!(!composer.is_pending());
+    // a different dead key types the first and waits for the second
+    composer.feed(ComposeInput::Dead('´'));
+    assert_eq!(
+        composer.feed(ComposeInput::Dead('`')),
+        ComposeOutput::Commit("´".to_string())
+    );
+    assert_eq!(
+        composer.feed(ComposeInput::Text("a")),
+        ComposeOutput::Commit("à".to_string())
+    );
+}
+
+#[test]
+fn test_compose_sequences() {
+    let mut composer = Composer::new();
+    let mut compose = |a: &str, b: &str| {
+        assert_eq!(composer.feed(ComposeInput::Compose), ComposeOutput::Pending);
+        assert_eq!(composer.feed(ComposeInput::Text(a)), ComposeOutput::Pending);
+        composer.feed(ComposeInput::Text(b))
+    };
+    assert_eq!(compose("o", "/"), ComposeOutput::Commit("ø".to_string()));
+    assert_eq!(compose("/", "o"), ComposeOutput::Commit("ø".to_string()));
+    assert_eq!(compose("s", "s"), ComposeOutput::Commit("ß".to_string()));
+    assert_eq!(compose("=", "e"), ComposeOutput::Commit("€".to_string()));
+    assert_eq!(compose("\"", "u"), ComposeOutput::Commit("ü".to_string()));
+    assert_eq!(compose("e", "'"), ComposeOutput::Commit("é".to_string()));
+    assert_eq!(compose("o", "a"), ComposeOutput::Commit("å".to_string()));
+    assert_eq!(compose("q", "q"), ComposeOutput::Commit("qq".to_string()));
+}
+
+#[test]
+fn test_compose_reset() {
+    let mut composer = Composer::new();
+    composer.feed(ComposeInput::Dead('´'));
+    composer.reset();
+    assert_eq!(
+        composer.feed(ComposeInput::Text("e")),
+        ComposeOutput::Commit("e".to_string())
+    );
+}
@@ -1,59 +1,82 @@
 extern crate gtk;
+use crate::modules::messages::Messages;
+use crate::modules::navigation::{Navigator, Screen};
 use crate::modules::virtual_keyboard;
 use gtk::prelude::*;
 use gtk::{Button, Label};
-use std::sync::Arc;
-use std::sync::Mutex;
-use virtual_keyboard::VirtualKeyboard;
+use std::rc::Rc;
+use virtual_keyboard::{PromptOptions, VirtualKeyboard};
 
+// IDs the screens are registered with the navigator under
+pub const HOME_SCREEN: &str = "home";
+pub const KEYBOARD_SCREEN: &str = "keyboard";
+
+// State shared between the screens. All of it lives on the GTK main thread,
+// so it's shared as an Rc rather than locked: callbacks can't deadlock on it,
+// however they nest. It isn't Send, so background threads don't touch it but
+// send their results to the main context instead.
 pub struct SharedData {
-    pub home_screen: Mutex<Option<HomeScreen>>,
-    pub virtual_keyboard: Option<VirtualKeyboard>,
+    pub navigator: Navigator,
+    pub messages: Messages<AppMessage>,
 }
 impl SharedData {
     pub fn new() -> SharedData {
         SharedData {
-            home_screen: Mutex::new(None),
-            virtual_keyboard: None,
+            navigator: Navigator::new(),
+            messages: Messages::new(),
         }
     }
 }
 
+// What background threads tell the screens
+#[derive(Debug, Clone)]
+pub enum AppMessage {
+    // shown on the home screen
+    Status(String),
+}
+
 pub struct HomeScreen {
     pub widget: gtk::Box,
 }
 
-impl HomeScreen {
-    fn show(&self) {
-        self.widget.show_all();
-    }
-    fn hide(&self) {
-        self.widget.hide();
+impl Screen for HomeScreen {
+    fn widget(&self) -> gtk::Widget {
+        self.widget.clone().upcast()
     }
-    fn process_keyboard_reply(
-        shared_data: &std::sync::MutexGuard<SharedData>, // already locked!
-        returnbutton: virtual_keyboard::DialogResult,
-    ) {
-        let virtual_keyboard = &shared_data.virtual_keyboard.as_ref().unwrap();
-        let binding = &shared_data.home_screen.lock().unwrap();
-        let home_screen = binding.as_ref().unwrap();
+}
 
-        match returnbutton {
-            virtual_keyboard::DialogResult::Ok => {
-                println!(
-                    "Keyboard click OK, val = {:?}",
-                    virtual_keyboard.input.lock().as_ref().unwrap()
-                );
+impl HomeScreen {
+    async fn keyboard_flow(shared_data: Rc<SharedData>) {
+        let navigator = &shared_data.navigator;
+        let virtual_keyboard = match navigator
+            .screen_widget(KEYBOARD_SCREEN)
+            .and_then(|widget| widget.downcast::<VirtualKeyboard>().ok())
+        {
+            Some(virtual_keyboard) => virtual_keyboard,
+            None => {
+                eprintln!("no keyboard registered as {:?}", KEYBOARD_SCREEN);
+                return;
             }
-            _ => {
+        };
+        let reply = virtual_keyboard.prompt("Please enter some text.", PromptOptions::default());
+        if let Err(error) = navigator.push(KEYBOARD_SCREEN) {
+            eprintln!("{}", error);
+            return;
+        }
+        match reply.await {
+            Some(text) => {
+                println!("Keyboard click OK, val = {:?}", text);
+            }
+            None => {
                 println!("Dialog cancelled.");
             }
         }
-        home_screen.show();
+        // back to whoever opened the keyboard
+        navigator.pop();
         virtual_keyboard.reset_input();
     }
 
-    fn button_callback(button: &gtk::Button, shared_data: &Arc<Mutex<SharedData>>) {
+    fn button_callback(button: &gtk::Button, shared_data: &Rc<SharedData>) {
         let button_label = button.label();
         match button_label {
             None => {
@@ -62,30 +85,27 @@ impl HomeScreen {
             }
             Some(label) => {
                 if label == "Keyboard" {
-                    let binding = shared_data.lock().expect("poison");
-                    let home_screen = binding.home_screen.lock().unwrap();
-                    home_screen.as_ref().unwrap().hide();
-                    let virtual_keyboard = binding.virtual_keyboard.as_ref().expect("not set");
-                    virtual_keyboard.reset_input();
-                    virtual_keyboard.show(
-                        move |shared, returnbutton: virtual_keyboard::DialogResult| {
-                            Self::process_keyboard_reply(shared, returnbutton);
-                        },
-                    );
+                    glib::MainContext::default()
+                        .spawn_local(Self::keyboard_flow(Rc::clone(shared_data)));
                 }
             }
         }
     }
 
-    fn _create_widget(shared_data: Arc<Mutex<SharedData>>) -> gtk::Box {
-        let shared_callback = move |button: &gtk::Button| {
-            let _ = Self::button_callback(button, &shared_data);
-        };
-
+    fn _create_widget(shared_data: Rc<SharedData>) -> gtk::Box {
         let home_screen = gtk::Box::new(gtk::Orientation::Vertical, 5);
 
         let label = Label::new(Some("Hello, World!"));
         home_screen.pack_start(&label, true, true, 0);
+        shared_data
+            .messages
+            .connect(HOME_SCREEN, move |message| match message {
+                AppMessage::Status(text) => label.set_text(text),
+            });
+
+        let shared_callback = move |button: &gtk::Button| {
+            let _ = Self::button_callback(button, &shared_data);
+        };
 
         let button_opendia = Button::with_label("Keyboard");
         button_opendia.connect_clicked(shared_callback.clone());
@@ -94,8 +114,8 @@ impl HomeScreen {
         home_screen.show_all();
         home_screen
     }
-    pub fn new(shared_data: Arc<Mutex<SharedData>>) -> HomeScreen {
-        let widget = HomeScreen::_create_widget(Arc::clone(&shared_data));
+    pub fn new(shared_data: Rc<SharedData>) -> HomeScreen {
+        let widget = HomeScreen::_create_widget(Rc::clone(&shared_data));
         let instance = HomeScreen { widget };
         instance
     }
@@ -0,0 +1,223 @@
+// Focus navigation across the keys, for units with a D-pad or a rotary
+// encoder instead of a touch panel.
+//
+// In grid focus mode the arrow keys move a focus highlight from key to key
+// and Enter presses the focused key; a rotary encoder steps through the keys
+// in reading order. Up and down go to the key in the next row that lies
+// under the middle of the focused one, so they follow the keys' widths:
+// from the space bar, up lands in the middle of the row above rather than on
+// its first key. Moves wrap around at the edges. The toggle key switches
+// between grid focus and the arrow keys moving the text cursor, as they do
+// otherwise.
+//
+// Like the Scanner, KeyFocus only keeps count; the virtual keyboard tells it
+// where its buttons are and maps the focus back onto them.
+
+// gdk keyvals: Tab toggles, Page Down and Page Up are the encoder's steps
+pub const DEFAULT_TOGGLE_KEY: u32 = 0xff09;
+pub const DEFAULT_NEXT_KEY: u32 = 0xff56;
+pub const DEFAULT_PREVIOUS_KEY: u32 = 0xff55;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusOptions {
+    // switches between grid focus and text cursor mode
+    pub toggle_key: u32,
+    // what a rotary encoder sends for a step clockwise and back
+    pub next_key: u32,
+    pub previous_key: u32,
+}
+
+impl Default for FocusOptions {
+    fn default() -> Self {
+        FocusOptions {
+            toggle_key: DEFAULT_TOGGLE_KEY,
+            next_key: DEFAULT_NEXT_KEY,
+            previous_key: DEFAULT_PREVIOUS_KEY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+    // in reading order, for a rotary encoder
+    Next,
+    Previous,
+}
+
+// Where a key is in its row, in the columns of the row's grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySpan {
+    pub column: i32,
+    pub width: i32,
+}
+
+impl KeySpan {
+    // twice the middle, to stay in whole columns
+    fn middle(&self) -> i32 {
+        2 * self.column + self.width
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyFocus {
+    rows: Vec<Vec<KeySpan>>,
+    // the focused key, as row and position in the row
+    focus: Option<(usize, usize)>,
+}
+
+impl KeyFocus {
+    pub fn new() -> KeyFocus {
+        KeyFocus::default()
+    }
+
+    pub fn focus(&self) -> Option<(usize, usize)> {
+        self.focus
+    }
+
+    // Sets where the keys are, e.g. after the layer changed. The focus stays
+    // where it was if there is still a key there, as there is on another
+    // layer of the same layout.
+    pub fn set_rows(&mut self, rows: Vec<Vec<KeySpan>>) {
+        self.rows = rows;
+        if let Some((row, key)) = self.focus {
+            if key >= self.keys_in(row) {
+                self.focus = self.first();
+            }
+        }
+    }
+
+    // Focuses the first key, unless one is focused already.
+    pub fn start(&mut self) {
+        if self.focus.is_none() {
+            self.focus = self.first();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.focus = None;
+    }
+
+    fn keys_in(&self, row: usize) -> usize {
+        self.rows.get(row).map_or(0, Vec::len)
+    }
+
+    fn first(&self) -> Option<(usize, usize)> {
+        (0..self.rows.len())
+            .find(|&row| self.keys_in(row) > 0)
+            .map(|row| (row, 0))
+    }
+
+    // the next row with keys, going down (or up), round to `row` itself
+    fn row_after(&self, row: usize, down: bool) -> usize {
+        let count = self.rows.len();
+        (1..=count)
+            .map(|step| {
+                if down {
+                    (row + step) % count
+                } else {
+                    (row + count - step % count) % count
+                }
+            })
+            .find(|&next| self.keys_in(next) > 0)
+            .unwrap_or(row)
+    }
+
+    // the key in `row` under the middle of `span`, or else the closest
+    fn key_under(&self, row: usize, span: KeySpan) -> usize {
+        let middle = span.middle();
+        let keys = &self.rows[row];
+        keys.iter()
+            .position(|key| 2 * key.column <= middle && middle < 2 * (key.column + key.width))
+            .or_else(|| (0..keys.len()).min_by_key(|&key| (keys[key].middle() - middle).abs()))
+            .unwrap_or(0)
+    }
+
+    pub fn move_focus(&mut self, direction: FocusDirection) {
+        let (row, key) = match self.focus {
+            Some(focus) => focus,
+            None => {
+                self.focus = self.first();
+                return;
+            }
+        };
+        let keys = self.keys_in(row);
+        if keys == 0 {
+            self.focus = self.first();
+            return;
+        }
+        self.focus = Some(match direction {
+            FocusDirection::Left => (row, (key + keys - 1) % keys),
+            FocusDirection::Right => (row, (key + 1) % keys),
+            FocusDirection::Up | FocusDirection::Down => {
+                let next = self.row_after(row, direction == FocusDirection::Down);
+                (next, self.key_under(next, self.rows[row][key]))
+            }
+            FocusDirection::Next if key + 1 < keys => (row, key + 1),
+            FocusDirection::Next => (self.row_after(row, true), 0),
+            FocusDirection::Previous if key > 0 => (row, key - 1),
+            FocusDirection::Previous => {
+                let previous = self.row_after(row, false);
+                (previous, self.keys_in(previous) - 1)
+            }
+        });
+    }
+}
+
+#[test]
+fn test_key_focus() {
+    let span = |column, width| KeySpan { column, width };
+    let mut focus = KeyFocus::new();
+    // keys of 4 columns each, a wide key, and an empty row
+    focus.set_rows(vec![
+        vec![span(0, 4), span(4, 4), span(8, 4), span(12, 4)],
+        vec![],
+        vec![span(2, 4), span(6, 8)],
+    ]);
+    assert_eq!(focus.focus(), None);
+    focus.start();
+    assert_eq!(focus.focus(), Some((0, 0)));
+    focus.move_focus(FocusDirection::Left);
+    assert_eq!(focus.focus(), Some((0, 3)));
+    focus.move_focus(FocusDirection::Right);
+    assert_eq!(focus.focus(), Some((0, 0)));
+
+    // down skips the empty row and lands under the middle of the key
+    focus.move_focus(FocusDirection::Down);
+    assert_eq!(focus.focus(), Some((2, 0)));
+    // up from the wide key goes to the key above its middle
+    focus.move_focus(FocusDirection::Right);
+    focus.move_focus(FocusDirection::Up);
+    assert_eq!(focus.focus(), Some((0, 2)));
+    // with nothing under the middle, the closest key
+    focus.move_focus(FocusDirection::Right);
+    focus.move_focus(FocusDirection::Down);
+    assert_eq!(focus.focus(), Some((2, 1)));
+    // and round from the bottom to the top
+    focus.move_focus(FocusDirection::Down);
+    assert_eq!(focus.focus(), Some((0, 2)));
+
+    // a rotary encoder goes through the keys in reading order
+    focus.move_focus(FocusDirection::Next);
+    focus.move_focus(FocusDirection::Next);
+    assert_eq!(focus.focus(), Some((2, 0)));
+    focus.move_focus(FocusDirection::Previous);
+    assert_eq!(focus.focus(), Some((0, 3)));
+    focus.move_focus(FocusDirection::Next);
+    focus.move_focus(FocusDirection::Next);
+    focus.move_focus(FocusDirection::Next);
+    assert_eq!(focus.focus(), Some((0, 0)));
+
+    // the focus stays put on another layer, but not off the keys
+    focus.move_focus(FocusDirection::Down);
+    focus.move_focus(FocusDirection::Right);
+    focus.set_rows(vec![vec![span(0, 8)], vec![], vec![span(0, 4), span(4, 4)]]);
+    assert_eq!(focus.focus(), Some((2, 1)));
+    focus.set_rows(vec![vec![span(0, 8)]]);
+    assert_eq!(focus.focus(), Some((0, 0)));
+    focus.stop();
+    assert_eq!(focus.focus(), None);
+}
@@ -0,0 +1,819 @@
+// Keyboard layouts for the virtual keyboard.
+//
+// A layout is a set of rows of keys, where every key carries one label per
+// layer (e.g. lower case, upper case, symbols), optionally with a second,
+// narrower set of rows for portrait screens. Layouts can be loaded from
+// TOML or JSON files so that new languages can be shipped without
+// recompiling; the UK QWERTY layout is compiled in as the default.
+use crate::modules::modifier_state::{LAYER_LOWER, LAYER_SYMBOLS, LAYER_UPPER};
+use crate::modules::virtual_keyboard::{
+    ID_BACKSPACE, ID_CANCEL, ID_CLEAR, ID_COMPOSE, ID_COPY, ID_CUT, ID_DELETE, ID_DELETE_WORD_BACK,
+    ID_DELETE_WORD_FORWARD, ID_END, ID_ENTER, ID_HOME, ID_INSERT, ID_LAYER_PREFIX, ID_LAYOUT,
+    ID_LEFT, ID_PASTE, ID_REDO, ID_RIGHT, ID_SELECT_ALL, ID_SELECT_END, ID_SELECT_HOME,
+    ID_SELECT_LEFT, ID_SELECT_RIGHT, ID_SHIFT, ID_SYMBOLS, ID_UNDO, ID_WORD_LEFT, ID_WORD_RIGHT,
+    SYMBOL_BACKSPACE, SYMBOL_CANCEL, SYMBOL_CLEAR, SYMBOL_COMPOSE, SYMBOL_COPY, SYMBOL_CUT,
+    SYMBOL_DELETE, SYMBOL_DELETE_WORD_BACK, SYMBOL_DELETE_WORD_FORWARD, SYMBOL_END, SYMBOL_ENTER,
+    SYMBOL_HOME, SYMBOL_INSERT, SYMBOL_LAYOUT, SYMBOL_LEFT, SYMBOL_PASTE, SYMBOL_REDO,
+    SYMBOL_RIGHT, SYMBOL_SELECT_ALL, SYMBOL_SELECT_END, SYMBOL_SELECT_HOME, SYMBOL_SELECT_LEFT,
+    SYMBOL_SELECT_RIGHT, SYMBOL_SHIFT, SYMBOL_SYMBOLS, SYMBOL_UNDO, SYMBOL_WORD_LEFT,
+    SYMBOL_WORD_RIGHT,
+};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+pub const ID_SPACER: &str = "spacer";
+const BUILTIN_LAYOUT: &str = include_str!("../../resources/layouts/uk_qwerty.toml");
+// keypad rows are this many key units wide
+const KEYPAD_WIDTH: f32 = 24.0;
+// Keys are laid out on a grid of equal columns, this many to a key unit, so
+// widths are rounded to a quarter of a key.
+pub const KEY_COLUMNS: f32 = 4.0;
+
+// Which arrangement of a layout to show; a keyboard that is taller than it
+// is wide is in portrait.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LayoutOrientation {
+    #[default]
+    Landscape,
+    Portrait,
+}
+
+impl LayoutOrientation {
+    pub fn for_size(width: i32, height: i32) -> LayoutOrientation {
+        if height > width {
+            LayoutOrientation::Portrait
+        } else {
+            LayoutOrientation::Landscape
+        }
+    }
+}
+
+// What kind of text the keyboard is asked for. Anything other than
+// FreeForm gets a dedicated keypad with large keys instead of the full
+// keyboard layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputPurpose {
+    #[default]
+    FreeForm,
+    Numeric,
+    Pin,
+    Phone,
+    Hex,
+    IpAddress,
+}
+
+impl InputPurpose {
+    // characters accepted by default for this purpose; "" accepts anything
+    pub fn accept(&self) -> &'static str {
+        match self {
+            InputPurpose::FreeForm => "",
+            InputPurpose::Numeric => "0123456789-.",
+            InputPurpose::Pin => "0123456789",
+            InputPurpose::Phone => "0123456789+*#",
+            InputPurpose::Hex => "0123456789ABCDEF",
+            InputPurpose::IpAddress => "0123456789.",
+        }
+    }
+
+    // whether the input should be hidden on screen
+    pub fn is_masked(&self) -> bool {
+        *self == InputPurpose::Pin
+    }
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    UnsupportedFormat(String),
+    UnknownLayout(String),
+    NoLayers,
+    NoRows,
+    EmptyRow {
+        row: usize,
+    },
+    InvalidWidth {
+        row: usize,
+        key: usize,
+        width: f32,
+    },
+    MissingLabels {
+        row: usize,
+        key: usize,
+    },
+    LabelCount {
+        row: usize,
+        key: usize,
+        expected: usize,
+        found: usize,
+    },
+    AlternatesCount {
+        row: usize,
+        key: usize,
+        layers: usize,
+        found: usize,
+    },
+    // a "layer:" key for a layer the layout doesn't have
+    UnknownLayer {
+        row: usize,
+        key: usize,
+        layer: String,
+    },
+    // a modifier key whose layer the layout doesn't have, e.g. a shift key
+    // without an "upper" layer
+    MissingLayer {
+        id: String,
+        layer: String,
+    },
+    // one of the above, in the portrait rows
+    Portrait(Box<LayoutError>),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::Io(e) => write!(f, "could not read layout file: {}", e),
+            LayoutError::Toml(e) => write!(f, "invalid TOML layout: {}", e),
+            LayoutError::Json(e) => write!(f, "invalid JSON layout: {}", e),
+            LayoutError::UnsupportedFormat(ext) => {
+                write!(f, "unsupported layout file format {:?}", ext)
+            }
+            LayoutError::UnknownLayout(name) => write!(f, "no layout named {:?}", name),
+            LayoutError::NoLayers => write!(f, "layout defines no layers"),
+            LayoutError::NoRows => write!(f, "layout defines no rows"),
+            LayoutError::EmptyRow { row } => write!(f, "row {} has no keys", row + 1),
+            LayoutError::InvalidWidth { row, key, width } => write!(
+                f,
+                "row {} key {}: width must be a positive number, got {}",
+                row + 1,
+                key + 1,
+                width
+            ),
+            LayoutError::MissingLabels { row, key } => {
+                write!(
+                    f,
+                    "row {} key {}: character key has no labels",
+                    row + 1,
+                    key + 1
+                )
+            }
+            LayoutError::LabelCount {
+                row,
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} key {}: expected 1 or {} labels, found {}",
+                row + 1,
+                key + 1,
+                expected,
+                found
+            ),
+            LayoutError::AlternatesCount {
+                row,
+                key,
+                layers,
+                found,
+            } => write!(
+                f,
+                "row {} key {}: {} alternates lists for {} layers",
+                row + 1,
+                key + 1,
+                found,
+                layers
+            ),
+            LayoutError::UnknownLayer { row, key, layer } => write!(
+                f,
+                "row {} key {}: no layer named {:?}",
+                row + 1,
+                key + 1,
+                layer
+            ),
+            LayoutError::MissingLayer { id, layer } => {
+                write!(f, "the {:?} key needs a layer named {:?}", id, layer)
+            }
+            LayoutError::Portrait(e) => write!(f, "portrait rows: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LayoutError::Io(e) => Some(e),
+            LayoutError::Toml(e) => Some(e),
+            LayoutError::Json(e) => Some(e),
+            LayoutError::Portrait(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LayoutError {
+    fn from(e: std::io::Error) -> Self {
+        LayoutError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for LayoutError {
+    fn from(e: toml::de::Error) -> Self {
+        LayoutError::Toml(e)
+    }
+}
+
+impl From<serde_json::Error> for LayoutError {
+    fn from(e: serde_json::Error) -> Self {
+        LayoutError::Json(e)
+    }
+}
+
+// The on-disk representation. This is converted into a KeyboardLayout by
+// `validate`, which fills in defaults and checks the label counts.
+#[derive(Deserialize)]
+struct LayoutFile {
+    name: String,
+    layers: Vec<String>,
+    rows: Vec<RowFile>,
+    #[serde(default)]
+    portrait: Vec<RowFile>,
+}
+
+#[derive(Deserialize)]
+struct RowFile {
+    keys: Vec<KeyFile>,
+}
+
+#[derive(Deserialize)]
+struct KeyFile {
+    #[serde(default = "default_key_width")]
+    width: f32,
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    alternates: Vec<Vec<String>>,
+}
+
+fn default_key_width() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyDef {
+    // width in key units; one unit is the width of a plain character key
+    pub width: f32,
+    // special key id (ID_SHIFT, ID_ENTER, ...), ID_SPACER or "" for plain keys
+    pub id: String,
+    // one label per layer of the layout
+    pub labels: Vec<String>,
+    // per layer, the characters offered when the key is held down
+    pub alternates: Vec<Vec<String>>,
+}
+
+impl KeyDef {
+    pub fn is_spacer(&self) -> bool {
+        self.id == ID_SPACER
+    }
+    pub fn label(&self, layer: usize) -> &str {
+        self.labels.get(layer).map(|l| l.as_str()).unwrap_or("")
+    }
+    pub fn alternates(&self, layer: usize) -> &[String] {
+        self.alternates
+            .get(layer)
+            .map(|a| a.as_slice())
+            .unwrap_or(&[])
+    }
+    // how many grid columns the key spans
+    pub fn columns(&self) -> i32 {
+        ((self.width * KEY_COLUMNS).round() as i32).max(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardLayout {
+    pub name: String,
+    pub layers: Vec<String>,
+    pub rows: Vec<Vec<KeyDef>>,
+    // the rows to show in portrait instead, if the layout has them
+    pub portrait: Option<Vec<Vec<KeyDef>>>,
+}
+
+impl Default for KeyboardLayout {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl KeyboardLayout {
+    // The compiled-in UK QWERTY layout.
+    pub fn builtin() -> KeyboardLayout {
+        Self::from_toml_str(BUILTIN_LAYOUT).expect("built-in layout is invalid")
+    }
+
+    pub fn from_toml_str(data: &str) -> Result<KeyboardLayout, LayoutError> {
+        let file: LayoutFile = toml::from_str(data)?;
+        Self::validate(file)
+    }
+
+    pub fn from_json_str(data: &str) -> Result<KeyboardLayout, LayoutError> {
+        let file: LayoutFile = serde_json::from_str(data)?;
+        Self::validate(file)
+    }
+
+    // Loads a layout file, picking the parser from the extension
+    // (`.toml` or `.json`).
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<KeyboardLayout, LayoutError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        match extension.as_str() {
+            "toml" => Self::from_toml_str(&std::fs::read_to_string(path)?),
+            "json" => Self::from_json_str(&std::fs::read_to_string(path)?),
+            _ => Err(LayoutError::UnsupportedFormat(extension)),
+        }
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer == name)
+    }
+
+    // The rows for an orientation; without portrait rows the landscape
+    // ones are used either way.
+    pub fn rows_for(&self, orientation: LayoutOrientation) -> &[Vec<KeyDef>] {
+        match (orientation, &self.portrait) {
+            (LayoutOrientation::Portrait, Some(portrait)) => portrait,
+            _ => &self.rows,
+        }
+    }
+
+    // The grid columns needed by the widest of the rows.
+    pub fn columns(rows: &[Vec<KeyDef>]) -> i32 {
+        rows.iter()
+            .map(|row| row.iter().map(|key| key.columns()).sum())
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Generates the keypad for an input purpose, or None for FreeForm.
+    pub fn keypad(purpose: InputPurpose) -> Option<KeyboardLayout> {
+        // the character keys, laid out in a grid; "" leaves a gap
+        let (name, grid, extra): (&str, Vec<[&str; 3]>, &[&str]) = match purpose {
+            InputPurpose::FreeForm => return None,
+            InputPurpose::Numeric => (
+                "keypad_numeric",
+                vec![
+                    ["7", "8", "9"],
+                    ["4", "5", "6"],
+                    ["1", "2", "3"],
+                    ["-", "0", "."],
+                ],
+                &[],
+            ),
+            InputPurpose::Pin => (
+                "keypad_pin",
+                vec![
+                    ["7", "8", "9"],
+                    ["4", "5", "6"],
+                    ["1", "2", "3"],
+                    ["", "0", ""],
+                ],
+                &[],
+            ),
+            InputPurpose::Phone => (
+                "keypad_phone",
+                vec![
+                    ["1", "2", "3"],
+                    ["4", "5", "6"],
+                    ["7", "8", "9"],
+                    ["*", "0", "#"],
+                ],
+                &["+"],
+            ),
+            InputPurpose::Hex => (
+                "keypad_hex",
+                vec![
+                    ["7", "8", "9"],
+                    ["4", "5", "6"],
+                    ["1", "2", "3"],
+                    ["", "0", ""],
+                ],
+                &["A", "B", "C", "D", "E", "F"],
+            ),
+            InputPurpose::IpAddress => (
+                "keypad_ip",
+                vec![
+                    ["7", "8", "9"],
+                    ["4", "5", "6"],
+                    ["1", "2", "3"],
+                    ["", "0", "."],
+                ],
+                &[],
+            ),
+        };
+        let key = |width: f32, id: &str, label: &str| KeyDef {
+            width,
+            id: id.to_string(),
+            labels: vec![label.to_string()],
+            alternates: vec![],
+        };
+        // every grid row ends in an editing key
+        let specials = [
+            (ID_BACKSPACE, SYMBOL_BACKSPACE),
+            (ID_LEFT, SYMBOL_LEFT),
+            (ID_RIGHT, SYMBOL_RIGHT),
+            (ID_DELETE, SYMBOL_DELETE),
+        ];
+        let key_width = KEYPAD_WIDTH / 4.0;
+        let mut rows: Vec<Vec<KeyDef>> = vec![];
+        // extra characters (hex digits, "+") go in a row above the grid
+        if !extra.is_empty() {
+            let width = KEYPAD_WIDTH / extra.len() as f32;
+            rows.push(extra.iter().map(|label| key(width, "", label)).collect());
+        }
+        for (cells, (id, symbol)) in grid.iter().zip(specials.iter()) {
+            let mut row: Vec<KeyDef> = cells
+                .iter()
+                .map(|label| {
+                    if label.is_empty() {
+                        key(key_width, ID_SPACER, "")
+                    } else {
+                        key(key_width, "", label)
+                    }
+                })
+                .collect();
+            row.push(key(key_width, id, symbol));
+            rows.push(row);
+        }
+        rows.push(vec![
+            key(KEYPAD_WIDTH / 2.0, ID_CANCEL, SYMBOL_CANCEL),
+            key(KEYPAD_WIDTH / 2.0, ID_ENTER, SYMBOL_ENTER),
+        ]);
+        Some(KeyboardLayout {
+            name: name.to_string(),
+            layers: vec!["keypad".to_string()],
+            rows,
+            portrait: None,
+        })
+    }
+
+    fn default_symbol(id: &str) -> Option<&'static str> {
+        let symbol = match id {
+            ID_BACKSPACE => SYMBOL_BACKSPACE,
+            ID_CANCEL => SYMBOL_CANCEL,
+            ID_CLEAR => SYMBOL_CLEAR,
+            ID_COMPOSE => SYMBOL_COMPOSE,
+            ID_COPY => SYMBOL_COPY,
+            ID_CUT => SYMBOL_CUT,
+            ID_DELETE => SYMBOL_DELETE,
+            ID_DELETE_WORD_BACK => SYMBOL_DELETE_WORD_BACK,
+            ID_DELETE_WORD_FORWARD => SYMBOL_DELETE_WORD_FORWARD,
+            ID_END => SYMBOL_END,
+            ID_ENTER => SYMBOL_ENTER,
+            ID_HOME => SYMBOL_HOME,
+            ID_INSERT => SYMBOL_INSERT,
+            ID_LAYOUT => SYMBOL_LAYOUT,
+            ID_LEFT => SYMBOL_LEFT,
+            ID_PASTE => SYMBOL_PASTE,
+            ID_REDO => SYMBOL_REDO,
+            ID_RIGHT => SYMBOL_RIGHT,
+            ID_SELECT_ALL => SYMBOL_SELECT_ALL,
+            ID_SELECT_END => SYMBOL_SELECT_END,
+            ID_SELECT_HOME => SYMBOL_SELECT_HOME,
+            ID_SELECT_LEFT => SYMBOL_SELECT_LEFT,
+            ID_SELECT_RIGHT => SYMBOL_SELECT_RIGHT,
+            ID_SHIFT => SYMBOL_SHIFT,
+            ID_SYMBOLS => SYMBOL_SYMBOLS,
+            ID_UNDO => SYMBOL_UNDO,
+            ID_WORD_LEFT => SYMBOL_WORD_LEFT,
+            ID_WORD_RIGHT => SYMBOL_WORD_RIGHT,
+            ID_SPACER => "",
+            _ => return None,
+        };
+        Some(symbol)
+    }
+
+    fn validate(file: LayoutFile) -> Result<KeyboardLayout, LayoutError> {
+        let layer_count = file.layers.len();
+        if layer_count == 0 {
+            return Err(LayoutError::NoLayers);
+        }
+        if file.rows.is_empty() {
+            return Err(LayoutError::NoRows);
+        }
+        let rows = Self::validate_rows(file.rows, &file.layers)?;
+        let portrait = if file.portrait.is_empty() {
+            None
+        } else {
+            Some(
+                Self::validate_rows(file.portrait, &file.layers)
+                    .map_err(|e| LayoutError::Portrait(Box::new(e)))?,
+            )
+        };
+        for keys in [Some(&rows), portrait.as_ref()].into_iter().flatten() {
+            Self::validate_modifier_layers(keys, &file.layers)?;
+        }
+        Ok(KeyboardLayout {
+            name: file.name,
+            layers: file.layers,
+            rows,
+            portrait,
+        })
+    }
+
+    // The modifier keys pick layers by name, so the layers they pick must
+    // be there; leaving any of them is back to "lower".
+    fn validate_modifier_layers(
+        rows: &[Vec<KeyDef>],
+        layers: &[String],
+    ) -> Result<(), LayoutError> {
+        for key in rows.iter().flatten() {
+            let needed: &[&str] = if key.id == ID_SHIFT {
+                &[LAYER_LOWER, LAYER_UPPER]
+            } else if key.id == ID_SYMBOLS {
+                &[LAYER_LOWER, LAYER_SYMBOLS]
+            } else if key.id.starts_with(ID_LAYER_PREFIX) {
+                &[LAYER_LOWER]
+            } else {
+                &[]
+            };
+            if let Some(layer) = needed
+                .iter()
+                .find(|layer| !layers.iter().any(|l| l == *layer))
+            {
+                return Err(LayoutError::MissingLayer {
+                    id: key.id.clone(),
+                    layer: layer.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_rows(
+        file_rows: Vec<RowFile>,
+        layers: &[String],
+    ) -> Result<Vec<Vec<KeyDef>>, LayoutError> {
+        let layer_count = layers.len();
+        let mut rows: Vec<Vec<KeyDef>> = vec![];
+        for (r, row) in file_rows.into_iter().enumerate() {
+            if row.keys.is_empty() {
+                return Err(LayoutError::EmptyRow { row: r });
+            }
+            let mut keys: Vec<KeyDef> = vec![];
+            for (k, key) in row.keys.into_iter().enumerate() {
+                if let Some(layer) = key.id.strip_prefix(ID_LAYER_PREFIX) {
+                    if !layers.iter().any(|l| l == layer) {
+                        return Err(LayoutError::UnknownLayer {
+                            row: r,
+                            key: k,
+                            layer: layer.to_string(),
+                        });
+                    }
+                }
+                if !(key.width > 0.0 && key.width.is_finite()) {
+                    return Err(LayoutError::InvalidWidth {
+                        row: r,
+                        key: k,
+                        width: key.width,
+                    });
+                }
+                // A single label (or a special key's default symbol) is
+                // used on every layer.
+                let labels = match key.labels.len() {
+                    0 => match Self::default_symbol(&key.id) {
+                        Some(symbol) => vec![symbol.to_string(); layer_count],
+                        None => return Err(LayoutError::MissingLabels { row: r, key: k }),
+                    },
+                    1 => vec![key.labels[0].clone(); layer_count],
+                    n if n == layer_count => key.labels,
+                    n => {
+                        return Err(LayoutError::LabelCount {
+                            row: r,
+                            key: k,
+                            expected: layer_count,
+                            found: n,
+                        })
+                    }
+                };
+                // Alternates may be left out for trailing layers (e.g. the
+                // symbols layer), but not given for layers that don't exist.
+                if key.alternates.len() > layer_count {
+                    return Err(LayoutError::AlternatesCount {
+                        row: r,
+                        key: k,
+                        layers: layer_count,
+                        found: key.alternates.len(),
+                    });
+                }
+                keys.push(KeyDef {
+                    width: key.width,
+                    id: key.id,
+                    labels,
+                    alternates: key.alternates,
+                });
+            }
+            rows.push(keys);
+        }
+        Ok(rows)
+    }
+}
+
+#[test]
+fn test_builtin_layout() {
+    let layout = KeyboardLayout::builtin();
+    assert_eq!(layout.name, "uk_qwerty");
+    assert_eq!(layout.layer_count(), 3);
+    assert_eq!(layout.rows.len(), 4);
+    assert_eq!(layout.rows[0][1].labels, ["q", "Q", "1"]);
+    assert_eq!(layout.rows[0][13].label(2), SYMBOL_BACKSPACE);
+    assert!(layout.rows[0][0].is_spacer());
+    assert_eq!(layout.rows[3][3].labels, [" ", " ", " "]);
+    assert_eq!(layout.layer_index("symbols"), Some(2));
+    assert_eq!(layout.layer_index("shifted"), None);
+    // e has accented alternates on the lower and upper layer only
+    assert_eq!(layout.rows[0][3].alternates(0), ["é", "è", "ê", "ë"]);
+    assert_eq!(layout.rows[0][3].alternates(1), ["É", "È", "Ê", "Ë"]);
+    assert!(layout.rows[0][3].alternates(2).is_empty());
+}
+
+#[test]
+fn test_portrait_rows() {
+    let layout = KeyboardLayout::builtin();
+    let portrait = layout.rows_for(LayoutOrientation::Portrait);
+    assert_eq!(portrait.len(), 5);
+    assert_eq!(portrait[0][0].labels, ["q", "Q", "1"]);
+    assert_eq!(layout.rows_for(LayoutOrientation::Landscape), layout.rows);
+    // every portrait row is ten keys wide
+    for row in portrait {
+        assert_eq!(KeyboardLayout::columns(std::slice::from_ref(row)), 40);
+    }
+    assert_eq!(KeyboardLayout::columns(&layout.rows), 66);
+    assert_eq!(layout.rows[3][1].columns(), 1);
+    // without portrait rows, the landscape ones are used
+    let keypad = KeyboardLayout::keypad(InputPurpose::Pin).unwrap();
+    assert_eq!(keypad.rows_for(LayoutOrientation::Portrait), keypad.rows);
+
+    assert_eq!(
+        LayoutOrientation::for_size(600, 1024),
+        LayoutOrientation::Portrait
+    );
+    assert_eq!(
+        LayoutOrientation::for_size(1280, 800),
+        LayoutOrientation::Landscape
+    );
+    let error = KeyboardLayout::from_toml_str(
+        "name = \"x\"\nlayers = [\"a\"]\n[[rows]]\nkeys = [{labels = [\"a\"]}]\n[[portrait]]\nkeys = []",
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&error, LayoutError::Portrait(e) if matches!(**e, LayoutError::EmptyRow { row: 0 }))
+    );
+    assert_eq!(error.to_string(), "portrait rows: row 1 has no keys");
+}
+
+#[test]
+fn test_json_layout() {
+    let layout = KeyboardLayout::from_json_str(
+        r#"{
+            "name": "tiny",
+            "layers": ["lower", "upper", "symbols", "extra"],
+            "rows": [{"keys": [
+                {"labels": ["a", "A", "1", "α"]},
+                {"id": "layer:extra", "labels": ["αβγ"]},
+                {"id": "ok", "width": 2}
+            ]}]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(layout.layer_count(), 4);
+    assert_eq!(layout.rows[0][0].label(3), "α");
+    assert_eq!(layout.rows[0][1].id, "layer:extra");
+    assert_eq!(layout.rows[0][2].width, 2.0);
+    assert_eq!(layout.rows[0][2].label(3), SYMBOL_ENTER);
+}
+
+#[test]
+fn test_invalid_layouts() {
+    assert!(matches!(
+        KeyboardLayout::from_toml_str("name = \"x\"\nlayers = []\n[[rows]]\nkeys = [{}]"),
+        Err(LayoutError::NoLayers)
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str("name = \"x\"\nlayers = [\"a\"]\nrows = []"),
+        Err(LayoutError::NoRows)
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str("name = \"x\"\nlayers = [\"a\"]\n[[rows]]\nkeys = []"),
+        Err(LayoutError::EmptyRow { row: 0 })
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str("name = \"x\"\nlayers = [\"a\"]\n[[rows]]\nkeys = [{}]"),
+        Err(LayoutError::MissingLabels { row: 0, key: 0 })
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str(
+            "name = \"x\"\nlayers = [\"a\", \"b\", \"c\"]\n[[rows]]\nkeys = [{labels = [\"a\", \"b\"]}]"
+        ),
+        Err(LayoutError::LabelCount {
+            expected: 3,
+            found: 2,
+            ..
+        })
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str(
+            "name = \"x\"\nlayers = [\"a\"]\n[[rows]]\nkeys = [{width = -1.0, labels = [\"a\"]}]"
+        ),
+        Err(LayoutError::InvalidWidth { .. })
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str(
+            "name = \"x\"\nlayers = [\"a\"]\n[[rows]]\nkeys = [{labels = [\"a\"], alternates = [[\"à\"], [\"á\"]]}]"
+        ),
+        Err(LayoutError::AlternatesCount {
+            layers: 1,
+            found: 2,
+            ..
+        })
+    ));
+    // modifier keys need their layers
+    let error = KeyboardLayout::from_toml_str(
+        "name = \"x\"\nlayers = [\"klein\", \"gross\"]\n[[rows]]\nkeys = [{id = \"shift\"}]",
+    )
+    .unwrap_err();
+    assert!(matches!(
+        &error,
+        LayoutError::MissingLayer { id, layer } if id == ID_SHIFT && layer == LAYER_LOWER
+    ));
+    assert_eq!(
+        error.to_string(),
+        "the \"shift\" key needs a layer named \"lower\""
+    );
+    assert!(matches!(
+        KeyboardLayout::from_toml_str(
+            "name = \"x\"\nlayers = [\"lower\", \"upper\"]\n[[rows]]\nkeys = [{id = \"symbols\"}]"
+        ),
+        Err(LayoutError::MissingLayer { layer, .. }) if layer == LAYER_SYMBOLS
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str(
+            "name = \"x\"\nlayers = [\"lower\"]\n[[rows]]\nkeys = [{id = \"layer:alt_gr\", labels = [\"AltGr\"]}]"
+        ),
+        Err(LayoutError::UnknownLayer { row: 0, key: 0, layer }) if layer == "alt_gr"
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_toml_str("this is not toml"),
+        Err(LayoutError::Toml(_))
+    ));
+    assert!(matches!(
+        KeyboardLayout::from_file("layout.xml"),
+        Err(LayoutError::UnsupportedFormat(_))
+    ));
+}
+
+#[test]
+fn test_shipped_layouts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/layouts");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let layout = KeyboardLayout::from_file(&path).unwrap();
+        assert_eq!(layout.layer_count(), 3, "{:?}", path);
+    }
+}
+
+#[test]
+fn test_keypads() {
+    assert!(KeyboardLayout::keypad(InputPurpose::FreeForm).is_none());
+    for purpose in [
+        InputPurpose::Numeric,
+        InputPurpose::Pin,
+        InputPurpose::Phone,
+        InputPurpose::Hex,
+        InputPurpose::IpAddress,
+    ] {
+        let layout = KeyboardLayout::keypad(purpose).unwrap();
+        assert_eq!(layout.layer_count(), 1);
+        // every character key must be accepted by the purpose
+        for key in layout.rows.iter().flatten() {
+            if key.id.is_empty() {
+                assert!(purpose.accept().contains(key.label(0)), "{:?}", key);
+            }
+        }
+        let last_row = layout.rows.last().unwrap();
+        assert_eq!(last_row[0].id, ID_CANCEL);
+        assert_eq!(last_row[1].id, ID_ENTER);
+    }
+    assert!(InputPurpose::Pin.is_masked());
+    assert!(!InputPurpose::Numeric.is_masked());
+}
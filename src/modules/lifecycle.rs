@@ -0,0 +1,89 @@
+// Timers and signal handlers a component only needs while on screen.
+//
+// They go into an `Owned`, which the component releases when its widget
+// comes off screen: a widget subclass from its unmap, a navigator screen
+// from `Screen::left()`. Dropping an `Owned` releases it as well, so nothing
+// keeps running for a component nobody can see.
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::time::Duration;
+
+// Timers and signal handlers that stop together.
+#[derive(Default)]
+pub struct Owned {
+    timers: RefCell<Vec<glib::SourceId>>,
+    handlers: RefCell<Vec<(glib::WeakRef<glib::Object>, glib::SignalHandlerId)>>,
+}
+
+impl Owned {
+    pub fn new() -> Owned {
+        Owned::default()
+    }
+
+    // Calls `tick` every `interval` until released.
+    pub fn timeout(&self, interval: Duration, mut tick: impl FnMut() + 'static) {
+        let timer = glib::timeout_add_local(interval, move || {
+            tick();
+            // only released, never stopped from inside, so the id stays valid
+            glib::Continue(true)
+        });
+        self.timers.borrow_mut().push(timer);
+    }
+
+    // Takes a handler connected to `object`, to disconnect it on release.
+    pub fn handler(&self, object: &impl IsA<glib::Object>, handler: glib::SignalHandlerId) {
+        self.handlers
+            .borrow_mut()
+            .push((object.upcast_ref::<glib::Object>().downgrade(), handler));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.borrow().is_empty() && self.handlers.borrow().is_empty()
+    }
+
+    // Stops everything; the Owned can be filled again afterwards.
+    pub fn release(&self) {
+        for timer in self.timers.take() {
+            timer.remove();
+        }
+        for (object, handler) in self.handlers.take() {
+            // gone with the object otherwise
+            if let Some(object) = object.upgrade() {
+                object.disconnect(handler);
+            }
+        }
+    }
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[test]
+fn test_owned_timers() {
+    use crate::modules::test_support::with_own_context;
+    use std::rc::Rc;
+    with_own_context(|context| {
+        let ticks = Rc::new(RefCell::new(0));
+        let owned = Owned::new();
+        let ticks_for_timer = Rc::clone(&ticks);
+        owned.timeout(Duration::ZERO, move || *ticks_for_timer.borrow_mut() += 1);
+        assert!(!owned.is_empty());
+        context.iteration(true);
+        let ticked = *ticks.borrow();
+        assert!(ticked > 0);
+        owned.release();
+        assert!(owned.is_empty());
+        while context.iteration(false) {}
+        assert_eq!(*ticks.borrow(), ticked);
+
+        // and dropping releases as well
+        let ticks_for_timer = Rc::clone(&ticks);
+        owned.timeout(Duration::ZERO, move || *ticks_for_timer.borrow_mut() += 1);
+        drop(owned);
+        while context.iteration(false) {}
+        assert_eq!(*ticks.borrow(), ticked);
+    });
+}
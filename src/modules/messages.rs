@@ -0,0 +1,147 @@
+// Messages from background threads to the UI.
+//
+// The UI state lives on the GTK main thread (see home_screen::SharedData),
+// so a thread reading a serial port or querying a database doesn't touch it.
+// Instead it sends a message through a `Sender` got from `Messages::sender`,
+// and the message is handed to the handlers on the main thread as soon as
+// the main loop gets to it, without anybody polling for it:
+//
+//     let sender = shared_data.messages.sender();
+//     std::thread::spawn(move || {
+//         let line = read_serial_line();
+//         let _ = sender.send(AppMessage::Status(line));
+//     });
+//
+// Handlers are registered per screen, so a screen can drop all of its
+// handlers at once when it goes away.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Sends messages to the main thread; Clone and Send as long as the message
+// type is Send. `send` fails after the `Messages` are gone.
+pub type Sender<M> = glib::Sender<M>;
+
+type Handler<M> = Rc<dyn Fn(&M)>;
+
+struct MessagesInner<M> {
+    sender: Sender<M>,
+    // in the order they were connected, with the screen they belong to
+    handlers: RefCell<Vec<(String, Handler<M>)>>,
+}
+
+pub struct Messages<M> {
+    inner: Rc<MessagesInner<M>>,
+}
+
+impl<M> Clone for Messages<M> {
+    fn clone(&self) -> Self {
+        Messages {
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<M: 'static> Default for Messages<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: 'static> Messages<M> {
+    // Messages are delivered on the main context of the thread that creates
+    // this, which is normally the GTK main thread.
+    pub fn new() -> Messages<M> {
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let inner = Rc::new(MessagesInner {
+            sender,
+            handlers: RefCell::new(vec![]),
+        });
+        let weak_inner = Rc::downgrade(&inner);
+        receiver.attach(
+            Some(&glib::MainContext::ref_thread_default()),
+            move |message| match weak_inner.upgrade() {
+                Some(inner) => {
+                    // handlers may connect and disconnect others, so they
+                    // run without the list borrowed
+                    let handlers: Vec<Handler<M>> = inner
+                        .handlers
+                        .borrow()
+                        .iter()
+                        .map(|(_, handler)| Rc::clone(handler))
+                        .collect();
+                    for handler in handlers {
+                        handler(&message);
+                    }
+                    glib::Continue(true)
+                }
+                // nobody left to deliver to
+                None => glib::Continue(false),
+            },
+        );
+        Messages { inner }
+    }
+
+    // for background threads; clone it for each one
+    pub fn sender(&self) -> Sender<M> {
+        self.inner.sender.clone()
+    }
+
+    // Every message goes to every handler; one that isn't interested in a
+    // message ignores it.
+    pub fn connect(&self, screen: &str, handler: impl Fn(&M) + 'static) {
+        self.inner
+            .handlers
+            .borrow_mut()
+            .push((screen.to_string(), Rc::new(handler)));
+    }
+
+    // Drops the handlers of a screen, e.g. when it is destroyed.
+    pub fn disconnect(&self, screen: &str) {
+        self.inner
+            .handlers
+            .borrow_mut()
+            .retain(|(handler_screen, _)| handler_screen != screen);
+    }
+}
+
+#[test]
+fn test_messages() {
+    use crate::modules::test_support::with_own_context;
+    with_own_context(|context| {
+        let messages = Messages::<u32>::new();
+        let received = Rc::new(RefCell::new(vec![]));
+        for screen in ["home", "settings"] {
+            let received = Rc::clone(&received);
+            messages.connect(screen, move |number| {
+                received.borrow_mut().push(format!("{} {}", screen, number))
+            });
+        }
+        let sender = messages.sender();
+        std::thread::spawn(move || {
+            sender.send(1).unwrap();
+            sender.send(2).unwrap();
+        })
+        .join()
+        .unwrap();
+        while context.iteration(false) {}
+        assert_eq!(
+            *received.borrow(),
+            ["home 1", "settings 1", "home 2", "settings 2"]
+        );
+
+        messages.disconnect("settings");
+        messages.sender().send(3).unwrap();
+        while context.iteration(false) {}
+        assert_eq!(received.borrow().last().unwrap(), "home 3");
+        assert_eq!(received.borrow().len(), 5);
+
+        // the channel closes with the first message after the UI end
+        // is gone, and sending fails from then on
+        let sender = messages.sender();
+        drop(messages);
+        sender.send(4).unwrap();
+        while context.iteration(false) {}
+        assert!(sender.send(5).is_err());
+        assert_eq!(received.borrow().len(), 5);
+    });
+}
@@ -1,2 +1,17 @@
+pub mod accessibility;
+pub mod compose;
 pub mod home_screen;
+pub mod key_focus;
+pub mod keyboard_layout;
+pub mod lifecycle;
+pub mod messages;
+pub mod modal;
+pub mod modifier_state;
+pub mod navigation;
+pub mod scanning;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod text_model;
+pub mod theme;
+pub mod undo_history;
 pub mod virtual_keyboard;
@@ -0,0 +1,64 @@
+// Awaiting modal screens.
+//
+// A modal screen (the virtual keyboard, a confirmation, ...) reports back
+// through a callback that it calls once when it closes. `modal` turns that
+// into a future, so a flow of several screens reads top to bottom:
+//
+//     glib::MainContext::default().spawn_local(async move {
+//         let name = keyboard.prompt("Name", PromptOptions::default()).await;
+//         ...
+//     });
+//
+// Futures from here must be awaited on the GTK main context (spawn_local),
+// since the screens live there.
+use futures_channel::oneshot;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// The result of a modal screen, or None if the screen dropped its callback
+// without calling it (e.g. it was shown again for something else).
+pub struct Modal<T> {
+    receiver: oneshot::Receiver<T>,
+}
+
+impl<T> Future for Modal<T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.receiver).poll(cx).map(Result::ok)
+    }
+}
+
+// Shows a screen right away, handing it the callback to close with. The
+// returned future doesn't borrow anything, so borrows taken to get at the
+// screen can end before awaiting it.
+pub fn modal<T: 'static>(show: impl FnOnce(Box<dyn FnOnce(T)>)) -> Modal<T> {
+    let (sender, receiver) = oneshot::channel();
+    show(Box::new(move |result| {
+        // nobody waiting any more is fine
+        let _ = sender.send(result);
+    }));
+    Modal { receiver }
+}
+
+#[cfg(test)]
+fn poll_once<T>(modal: &mut Modal<T>) -> Poll<Option<T>> {
+    let mut cx = Context::from_waker(std::task::Waker::noop());
+    Pin::new(modal).poll(&mut cx)
+}
+
+#[test]
+fn test_modal() {
+    let mut callback = None;
+    let mut answer = modal(|done| callback = Some(done));
+    assert_eq!(poll_once(&mut answer), Poll::Pending);
+    callback.take().unwrap()("hello".to_string());
+    assert_eq!(
+        poll_once(&mut answer),
+        Poll::Ready(Some("hello".to_string()))
+    );
+    // a screen that goes away without answering
+    let mut dropped = modal(|done: Box<dyn FnOnce(u32)>| drop(done));
+    assert_eq!(poll_once(&mut dropped), Poll::Ready(None));
+}
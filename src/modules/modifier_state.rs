@@ -0,0 +1,192 @@
+// Shift / caps lock / symbols state of the virtual keyboard.
+//
+// Tapping shift once gives a one-shot shift that reverts after the next
+// character; tapping it twice in quick succession locks it (caps lock) until
+// it is tapped again. The symbols key switches to the symbols layer and back,
+// independently of shift. Any other layer of a layout is reached through a
+// key of its own, which switches to that layer and back in the same way.
+use std::time::{Duration, Instant};
+
+pub const DOUBLE_TAP_INTERVAL: Duration = Duration::from_millis(400);
+pub const LAYER_LOWER: &str = "lower";
+pub const LAYER_UPPER: &str = "upper";
+pub const LAYER_SYMBOLS: &str = "symbols";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftState {
+    Off,
+    OneShot,
+    Locked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifierState {
+    shift: ShiftState,
+    symbols: bool,
+    // a layer other than lower, upper and symbols, while it is showing
+    layer: Option<String>,
+    last_shift_tap: Option<Instant>,
+}
+
+impl Default for ModifierState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModifierState {
+    pub fn new() -> ModifierState {
+        ModifierState {
+            shift: ShiftState::Off,
+            symbols: false,
+            layer: None,
+            last_shift_tap: None,
+        }
+    }
+
+    pub fn shift(&self) -> ShiftState {
+        self.shift
+    }
+
+    pub fn symbols(&self) -> bool {
+        self.symbols
+    }
+
+    pub fn layer(&self) -> Option<&str> {
+        self.layer.as_deref()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn shift_tapped(&mut self, now: Instant) {
+        let double_tap = match self.last_shift_tap {
+            Some(last) => now.duration_since(last) <= DOUBLE_TAP_INTERVAL,
+            None => false,
+        };
+        self.last_shift_tap = Some(now);
+        if self.layer.take().is_some() || self.symbols {
+            // there is no shifted symbols (or other) layer; go back to the
+            // letters
+            self.symbols = false;
+            self.shift = ShiftState::OneShot;
+            return;
+        }
+        self.shift = match self.shift {
+            ShiftState::Off => ShiftState::OneShot,
+            ShiftState::OneShot if double_tap => ShiftState::Locked,
+            ShiftState::OneShot => ShiftState::Off,
+            ShiftState::Locked => ShiftState::Off,
+        };
+        if self.shift != ShiftState::OneShot {
+            // a third quick tap must not count as another double tap
+            self.last_shift_tap = None;
+        }
+    }
+
+    pub fn symbols_tapped(&mut self) {
+        // from a named layer, always to the symbols
+        self.symbols = self.layer.take().is_some() || !self.symbols;
+    }
+
+    // the key of a named layer; tapped again, it goes back
+    pub fn layer_tapped(&mut self, layer: &str) {
+        if self.layer.as_deref() == Some(layer) {
+            self.layer = None;
+        } else {
+            self.layer = Some(layer.to_string());
+        }
+    }
+
+    // to be called after a character has been typed
+    pub fn character_typed(&mut self) {
+        if self.shift == ShiftState::OneShot {
+            self.shift = ShiftState::Off;
+        }
+    }
+
+    // name of the layout layer to show for the current state
+    pub fn layer_name(&self) -> &str {
+        if let Some(layer) = &self.layer {
+            layer
+        } else if self.symbols {
+            LAYER_SYMBOLS
+        } else if self.shift == ShiftState::Off {
+            LAYER_LOWER
+        } else {
+            LAYER_UPPER
+        }
+    }
+}
+
+#[test]
+fn test_one_shot_shift() {
+    let mut state = ModifierState::new();
+    let start = Instant::now();
+    assert_eq!(state.layer_name(), LAYER_LOWER);
+    state.shift_tapped(start);
+    assert_eq!(state.shift(), ShiftState::OneShot);
+    assert_eq!(state.layer_name(), LAYER_UPPER);
+    state.character_typed();
+    assert_eq!(state.shift(), ShiftState::Off);
+    assert_eq!(state.layer_name(), LAYER_LOWER);
+}
+
+#[test]
+fn test_caps_lock() {
+    let mut state = ModifierState::new();
+    let start = Instant::now();
+    state.shift_tapped(start);
+    state.shift_tapped(start + Duration::from_millis(200));
+    assert_eq!(state.shift(), ShiftState::Locked);
+    state.character_typed();
+    state.character_typed();
+    assert_eq!(state.layer_name(), LAYER_UPPER);
+    // a quick third tap unlocks rather than re-locking
+    state.shift_tapped(start + Duration::from_millis(300));
+    assert_eq!(state.shift(), ShiftState::Off);
+}
+
+#[test]
+fn test_slow_second_tap_cancels_shift() {
+    let mut state = ModifierState::new();
+    let start = Instant::now();
+    state.shift_tapped(start);
+    state.shift_tapped(start + DOUBLE_TAP_INTERVAL + Duration::from_millis(1));
+    assert_eq!(state.shift(), ShiftState::Off);
+}
+
+#[test]
+fn test_symbols() {
+    let mut state = ModifierState::new();
+    state.symbols_tapped();
+    assert_eq!(state.layer_name(), LAYER_SYMBOLS);
+    state.character_typed();
+    assert_eq!(state.layer_name(), LAYER_SYMBOLS);
+    state.symbols_tapped();
+    assert_eq!(state.layer_name(), LAYER_LOWER);
+    state.symbols_tapped();
+    state.shift_tapped(Instant::now());
+    assert!(!state.symbols());
+    assert_eq!(state.shift(), ShiftState::OneShot);
+}
+
+#[test]
+fn test_named_layer() {
+    let mut state = ModifierState::new();
+    state.layer_tapped("extra");
+    assert_eq!(state.layer(), Some("extra"));
+    assert_eq!(state.layer_name(), "extra");
+    state.character_typed();
+    assert_eq!(state.layer_name(), "extra");
+    state.layer_tapped("extra");
+    assert_eq!(state.layer_name(), LAYER_LOWER);
+    // the other modifier keys leave the layer for their own
+    state.layer_tapped("extra");
+    state.symbols_tapped();
+    assert_eq!(state.layer_name(), LAYER_SYMBOLS);
+    state.layer_tapped("extra");
+    state.shift_tapped(Instant::now());
+    assert_eq!(state.layer_name(), LAYER_UPPER);
+}
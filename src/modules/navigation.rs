@@ -0,0 +1,202 @@
+// Navigation between screens.
+//
+// Screens are registered with the Navigator under an ID and live as pages of
+// a gtk::Stack. Pushing a screen slides it in on top of the current one, and
+// popping it slides back to whichever screen pushed it. A screen can be
+// pushed for a result, which the caller awaits; the screen hands it back by
+// popping itself with `pop_with`.
+use crate::modules::modal::{modal, Modal};
+use gtk::prelude::*;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// how long the slide between two screens takes, in ms
+pub const TRANSITION_DURATION: u32 = 200;
+
+pub trait Screen {
+    // the page shown in the stack
+    fn widget(&self) -> gtk::Widget;
+
+    // the screen has come to the top, either pushed or popped back to
+    fn entered(&self) {}
+
+    // the screen is no longer on top, either popped or covered by another
+    fn left(&self) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NavigationError {
+    UnknownScreen(String),
+}
+
+impl fmt::Display for NavigationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavigationError::UnknownScreen(id) => write!(f, "no screen registered as {:?}", id),
+        }
+    }
+}
+
+impl std::error::Error for NavigationError {}
+
+type ResultCallback = Box<dyn FnOnce(Box<dyn Any>)>;
+
+// A screen on the stack, with what to do with the result it pops with.
+struct Entry {
+    id: String,
+    on_result: Option<ResultCallback>,
+}
+
+struct NavigatorInner {
+    stack: gtk::Stack,
+    screens: RefCell<HashMap<String, Rc<dyn Screen>>>,
+    // bottom (the root screen) first
+    history: RefCell<Vec<Entry>>,
+}
+
+#[derive(Clone)]
+pub struct Navigator {
+    inner: Rc<NavigatorInner>,
+}
+
+impl Default for Navigator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Navigator {
+    pub fn new() -> Navigator {
+        let stack = gtk::Stack::new();
+        stack.set_transition_duration(TRANSITION_DURATION);
+        Navigator {
+            inner: Rc::new(NavigatorInner {
+                stack,
+                screens: RefCell::new(HashMap::new()),
+                history: RefCell::new(vec![]),
+            }),
+        }
+    }
+
+    // to be packed into the window
+    pub fn widget(&self) -> gtk::Stack {
+        self.inner.stack.clone()
+    }
+
+    pub fn register(&self, id: &str, screen: impl Screen + 'static) {
+        self.inner.stack.add_named(&screen.widget(), id);
+        self.inner
+            .screens
+            .borrow_mut()
+            .insert(id.to_string(), Rc::new(screen));
+    }
+
+    fn screen(&self, id: &str) -> Option<Rc<dyn Screen>> {
+        self.inner.screens.borrow().get(id).cloned()
+    }
+
+    // The page of a registered screen, e.g. to downcast it to the widget
+    // type it is.
+    pub fn screen_widget(&self, id: &str) -> Option<gtk::Widget> {
+        self.screen(id).map(|screen| screen.widget())
+    }
+
+    // the screen on top
+    pub fn current(&self) -> Option<String> {
+        self.inner
+            .history
+            .borrow()
+            .last()
+            .map(|entry| entry.id.clone())
+    }
+
+    // how many screens are stacked, the root included
+    pub fn depth(&self) -> usize {
+        self.inner.history.borrow().len()
+    }
+
+    // The first screen pushed is the root, which can't be popped.
+    pub fn push(&self, id: &str) -> Result<(), NavigationError> {
+        self.push_entry(id, None)
+    }
+
+    // Resolves to what the screen pops with, or None if it pops without a
+    // result (or one of another type), or isn't registered.
+    pub fn push_for_result<T: 'static>(&self, id: &str) -> Modal<T> {
+        modal(|done: Box<dyn FnOnce(T)>| {
+            let on_result: ResultCallback = Box::new(move |result| {
+                if let Ok(result) = result.downcast::<T>() {
+                    done(*result);
+                }
+            });
+            if let Err(error) = self.push_entry(id, Some(on_result)) {
+                eprintln!("{}", error);
+            }
+        })
+    }
+
+    fn push_entry(
+        &self,
+        id: &str,
+        on_result: Option<ResultCallback>,
+    ) -> Result<(), NavigationError> {
+        let screen = self
+            .screen(id)
+            .ok_or_else(|| NavigationError::UnknownScreen(id.to_string()))?;
+        let covered = self.current().and_then(|id| self.screen(&id));
+        self.inner.history.borrow_mut().push(Entry {
+            id: id.to_string(),
+            on_result,
+        });
+        // the hooks run with nothing borrowed, so they may navigate too
+        if let Some(covered) = covered {
+            covered.left();
+        }
+        self.show(id, &screen, gtk::StackTransitionType::SlideLeft);
+        screen.entered();
+        Ok(())
+    }
+
+    // Back to the screen below; the one that pushed for a result gets None.
+    pub fn pop(&self) {
+        self.pop_entry(None);
+    }
+
+    pub fn pop_with<T: 'static>(&self, result: T) {
+        self.pop_entry(Some(Box::new(result)));
+    }
+
+    fn pop_entry(&self, result: Option<Box<dyn Any>>) {
+        let (popped, uncovered) = {
+            let mut history = self.inner.history.borrow_mut();
+            if history.len() < 2 {
+                // the root stays
+                return;
+            }
+            let popped = history.pop().unwrap();
+            let uncovered = history.last().unwrap().id.clone();
+            (popped, uncovered)
+        };
+        if let Some(screen) = self.screen(&popped.id) {
+            screen.left();
+        }
+        if let Some(screen) = self.screen(&uncovered) {
+            self.show(&uncovered, &screen, gtk::StackTransitionType::SlideRight);
+            screen.entered();
+        }
+        // without a result the callback is dropped, which resolves the
+        // caller's future to None
+        if let (Some(on_result), Some(result)) = (popped.on_result, result) {
+            on_result(result);
+        }
+    }
+
+    fn show(&self, id: &str, screen: &Rc<dyn Screen>, transition: gtk::StackTransitionType) {
+        // the stack skips hidden pages
+        screen.widget().show();
+        self.inner.stack.set_visible_child_full(id, transition);
+    }
+}
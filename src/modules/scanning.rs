@@ -0,0 +1,246 @@
+// Switch access: scanning the keys for users who can work a single switch.
+//
+// While scanning, a highlight steps through the keys on a timer and the
+// switch picks whatever is highlighted. Row/column scanning highlights whole
+// rows first; the switch picks a row, the highlight then steps through its
+// keys and the switch presses one. A row passed through without a press
+// goes back to scanning rows, starting with that one. Linear scanning steps
+// through every key in turn: slower to get anywhere, but one press a key.
+//
+// The Scanner only keeps count. It is told how many keys each row has, and
+// the virtual keyboard maps what it highlights onto its buttons.
+use std::time::Duration;
+
+pub const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_millis(1000);
+// the gdk keyval of F12, which types nothing, so a physical keyboard still
+// types everything while scanning; most switch interfaces can send it
+pub const DEFAULT_SWITCH_KEY: u32 = 0xffc9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScanMode {
+    #[default]
+    RowColumn,
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub mode: ScanMode,
+    // how long each row or key stays highlighted
+    pub interval: Duration,
+    // After a key press, or a pass over the rows without one, scanning
+    // starts over from the top. Otherwise it stops until the switch is
+    // pressed again.
+    pub auto_restart: bool,
+    // the gdk keyval of the physical key that works as the switch
+    pub switch_key: u32,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            mode: ScanMode::default(),
+            interval: DEFAULT_SCAN_INTERVAL,
+            auto_restart: true,
+            switch_key: DEFAULT_SWITCH_KEY,
+        }
+    }
+}
+
+// What is highlighted: nothing (not scanning), a row, or a key as row and
+// position in the row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Highlight {
+    #[default]
+    None,
+    Row(usize),
+    Key(usize, usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scanner {
+    options: ScanOptions,
+    // the number of keys in each row
+    rows: Vec<usize>,
+    highlight: Highlight,
+}
+
+impl Scanner {
+    pub fn new(options: ScanOptions) -> Scanner {
+        Scanner {
+            options,
+            ..Scanner::default()
+        }
+    }
+
+    pub fn options(&self) -> &ScanOptions {
+        &self.options
+    }
+
+    // New options apply from the next scan on.
+    pub fn set_options(&mut self, options: ScanOptions) {
+        self.options = options;
+        self.stop();
+    }
+
+    pub fn highlight(&self) -> Highlight {
+        self.highlight
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.highlight != Highlight::None
+    }
+
+    // Sets how many keys each row has, e.g. after the layer changed; a
+    // running scan starts over, since the old position means nothing now.
+    pub fn set_rows(&mut self, rows: Vec<usize>) {
+        self.rows = rows;
+        if self.is_running() {
+            self.start();
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.highlight = self.first();
+    }
+
+    pub fn stop(&mut self) {
+        self.highlight = Highlight::None;
+    }
+
+    fn keys_in(&self, row: usize) -> usize {
+        self.rows.get(row).copied().unwrap_or(0)
+    }
+
+    // the first row with keys at or after `row`
+    fn row_from(&self, row: usize) -> Option<usize> {
+        (row..self.rows.len()).find(|&row| self.keys_in(row) > 0)
+    }
+
+    fn first(&self) -> Highlight {
+        match (self.row_from(0), self.options.mode) {
+            (None, _) => Highlight::None,
+            (Some(row), ScanMode::RowColumn) => Highlight::Row(row),
+            (Some(row), ScanMode::Linear) => Highlight::Key(row, 0),
+        }
+    }
+
+    // where a scan goes once it has passed the last row
+    fn restart(&self) -> Highlight {
+        if self.options.auto_restart {
+            self.first()
+        } else {
+            Highlight::None
+        }
+    }
+
+    // Steps the highlight on; called every interval.
+    pub fn tick(&mut self) {
+        self.highlight = match (self.highlight, self.options.mode) {
+            (Highlight::None, _) => Highlight::None,
+            (Highlight::Row(row), _) => match self.row_from(row + 1) {
+                Some(next) => Highlight::Row(next),
+                None => self.restart(),
+            },
+            (Highlight::Key(row, key), _) if key + 1 < self.keys_in(row) => {
+                Highlight::Key(row, key + 1)
+            }
+            (Highlight::Key(row, _), ScanMode::RowColumn) => Highlight::Row(row),
+            (Highlight::Key(row, _), ScanMode::Linear) => match self.row_from(row + 1) {
+                Some(next) => Highlight::Key(next, 0),
+                None => self.restart(),
+            },
+        };
+    }
+
+    // The switch was pressed. Returns the key to press, as row and position
+    // in the row, if that picked one. While stopped the switch starts a scan.
+    pub fn switch(&mut self) -> Option<(usize, usize)> {
+        match self.highlight {
+            Highlight::None => {
+                self.start();
+                None
+            }
+            Highlight::Row(row) => {
+                self.highlight = Highlight::Key(row, 0);
+                None
+            }
+            Highlight::Key(row, key) => {
+                self.highlight = self.restart();
+                Some((row, key))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_row_column_scanning() {
+    let mut scanner = Scanner::new(ScanOptions::default());
+    // the empty row is passed over
+    scanner.set_rows(vec![2, 0, 3]);
+    assert!(!scanner.is_running());
+    assert_eq!(scanner.switch(), None);
+    assert_eq!(scanner.highlight(), Highlight::Row(0));
+    scanner.tick();
+    assert_eq!(scanner.highlight(), Highlight::Row(2));
+    scanner.tick();
+    assert_eq!(scanner.highlight(), Highlight::Row(0));
+
+    // a row passed through without a press goes back to scanning rows
+    assert_eq!(scanner.switch(), None);
+    assert_eq!(scanner.highlight(), Highlight::Key(0, 0));
+    scanner.tick();
+    assert_eq!(scanner.highlight(), Highlight::Key(0, 1));
+    scanner.tick();
+    assert_eq!(scanner.highlight(), Highlight::Row(0));
+
+    scanner.tick();
+    scanner.switch();
+    scanner.tick();
+    assert_eq!(scanner.switch(), Some((2, 1)));
+    assert_eq!(scanner.highlight(), Highlight::Row(0));
+
+    // new rows start the scan over
+    scanner.tick();
+    scanner.set_rows(vec![0, 4]);
+    assert_eq!(scanner.highlight(), Highlight::Row(1));
+}
+
+#[test]
+fn test_linear_scanning_without_restart() {
+    let mut scanner = Scanner::new(ScanOptions {
+        mode: ScanMode::Linear,
+        auto_restart: false,
+        ..ScanOptions::default()
+    });
+    scanner.set_rows(vec![2, 1]);
+    scanner.start();
+    let mut highlights = vec![scanner.highlight()];
+    for _ in 0..3 {
+        scanner.tick();
+        highlights.push(scanner.highlight());
+    }
+    assert_eq!(
+        highlights,
+        [
+            Highlight::Key(0, 0),
+            Highlight::Key(0, 1),
+            Highlight::Key(1, 0),
+            Highlight::None
+        ]
+    );
+
+    // a press stops the scan, and the next one starts it again
+    scanner.switch();
+    scanner.tick();
+    assert_eq!(scanner.switch(), Some((0, 1)));
+    assert!(!scanner.is_running());
+    scanner.tick();
+    assert!(!scanner.is_running());
+    assert_eq!(scanner.switch(), None);
+    assert_eq!(scanner.highlight(), Highlight::Key(0, 0));
+
+    // no keys, nothing to scan
+    scanner.set_rows(vec![]);
+    assert!(!scanner.is_running());
+}
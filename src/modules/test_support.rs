@@ -0,0 +1,9 @@
+// Helpers shared by the unit tests.
+
+// Runs `test` with a main context of its own as the thread default, which
+// it is given to iterate. Timers and channels then stay off the default
+// context, which other tests, on other threads, may be running.
+pub(crate) fn with_own_context(test: impl FnOnce(&glib::MainContext)) {
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| test(&context)).unwrap();
+}
@@ -0,0 +1,553 @@
+// Text and cursor state of the virtual keyboard input.
+//
+// This has no GTK dependency, so the editing behaviour can be tested without
+// a display. Positions count grapheme clusters rather than chars: an e with a
+// combining accent, a flag or a family emoji joined with zero width joiners
+// is one character to the user, so it is one cursor stop here, and backspace
+// and delete remove it as a whole.
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Home,
+    End,
+}
+
+// number of grapheme clusters in text
+pub fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+// byte offset of the grapheme at position (or the end of text)
+fn byte_offset(text: &str, position: usize) -> usize {
+    text.grapheme_indices(true)
+        .nth(position)
+        .map(|(offset, _)| offset)
+        .unwrap_or(text.len())
+}
+
+// the graphemes from start up to end
+pub fn slice(text: &str, start: usize, end: usize) -> &str {
+    let start_offset = byte_offset(text, start);
+    let end_offset = byte_offset(text, end.max(start));
+    &text[start_offset..end_offset]
+}
+
+// text with the graphemes from start up to end replaced
+pub fn splice(text: &str, start: usize, end: usize, replacement: &str) -> String {
+    let start_offset = byte_offset(text, start);
+    let end_offset = byte_offset(text, end.max(start));
+    format!(
+        "{}{}{}",
+        &text[..start_offset],
+        replacement,
+        &text[end_offset..]
+    )
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .map(|c| c.is_alphanumeric() || c == '_')
+        .unwrap_or(false)
+}
+
+// the start of the word before position, skipping any spaces and
+// punctuation in between (Ctrl+Left, Ctrl+Backspace)
+pub fn word_start_before(text: &str, position: usize) -> usize {
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    let mut pos = position.min(graphemes.len());
+    while pos > 0 && !is_word(graphemes[pos - 1]) {
+        pos -= 1;
+    }
+    while pos > 0 && is_word(graphemes[pos - 1]) {
+        pos -= 1;
+    }
+    pos
+}
+
+// just past the end of the word after position (Ctrl+Right, Ctrl+Delete)
+pub fn word_end_after(text: &str, position: usize) -> usize {
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    let mut pos = position.min(graphemes.len());
+    while pos < graphemes.len() && !is_word(graphemes[pos]) {
+        pos += 1;
+    }
+    while pos < graphemes.len() && is_word(graphemes[pos]) {
+        pos += 1;
+    }
+    pos
+}
+
+// the (start, end) of the selection between anchor and cursor, if any
+pub fn selection_range(anchor: Option<usize>, cursor: usize) -> Option<(usize, usize)> {
+    match anchor {
+        Some(anchor) if anchor != cursor => Some((anchor.min(cursor), anchor.max(cursor))),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextModel {
+    text: String,
+    cursor: usize,
+    // the other end of the selection, which runs up to the cursor
+    anchor: Option<usize>,
+    // insert typed text at the cursor rather than overwriting
+    insert_mode: bool,
+}
+
+impl TextModel {
+    pub fn new() -> TextModel {
+        TextModel {
+            text: String::new(),
+            cursor: 0,
+            anchor: None,
+            insert_mode: false,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn len(&self) -> usize {
+        grapheme_count(&self.text)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn insert_mode(&self) -> bool {
+        self.insert_mode
+    }
+
+    pub fn toggle_insert_mode(&mut self) {
+        self.insert_mode = !self.insert_mode;
+    }
+
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        selection_range(self.anchor, self.cursor)
+    }
+
+    pub fn selected_text(&self) -> Option<&str> {
+        let (start, end) = self.selection()?;
+        Some(slice(&self.text, start, end))
+    }
+
+    // the text before, on and after the cursor, for drawing it
+    pub fn pre_cursor(&self) -> &str {
+        slice(&self.text, 0, self.cursor)
+    }
+
+    pub fn on_cursor(&self) -> Option<&str> {
+        let grapheme = slice(&self.text, self.cursor, self.cursor + 1);
+        if grapheme.is_empty() {
+            None
+        } else {
+            Some(grapheme)
+        }
+    }
+
+    pub fn post_cursor(&self) -> &str {
+        slice(&self.text, self.cursor + 1, usize::MAX)
+    }
+
+    // the same input with every character shown as `symbol`, e.g. for a PIN
+    pub fn masked(&self, symbol: &str) -> TextModel {
+        TextModel {
+            text: symbol.repeat(self.len()),
+            ..self.clone()
+        }
+    }
+
+    // replace everything, e.g. when going back in the undo history
+    pub fn set_text(&mut self, text: &str, cursor: usize) {
+        self.text = text.to_string();
+        self.anchor = None;
+        self.cursor = cursor.min(self.len());
+    }
+
+    pub fn clear(&mut self) {
+        self.set_text("", 0);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.anchor = None;
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.len();
+    }
+
+    pub fn move_cursor(&mut self, movement: Movement, selecting: bool) {
+        let position = match movement {
+            Movement::Left => self.cursor.saturating_sub(1),
+            Movement::Right => self.cursor + 1,
+            Movement::WordLeft => word_start_before(&self.text, self.cursor),
+            Movement::WordRight => word_end_after(&self.text, self.cursor),
+            Movement::Home => 0,
+            Movement::End => self.len(),
+        };
+        if !selecting {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+        self.cursor = position.min(self.len());
+    }
+
+    fn replace(&mut self, start: usize, end: usize, replacement: &str) {
+        // the cursor goes after the replacement; counting the graphemes up to
+        // there (rather than adding) lets a combining accent merge with the
+        // character before it
+        let text = splice(&self.text, start, end, replacement);
+        let prefix_len = byte_offset(&self.text, start) + replacement.len();
+        self.cursor = grapheme_count(&text[..prefix_len]);
+        self.text = text;
+        self.anchor = None;
+    }
+
+    // returns whether there was a selection to delete
+    pub fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((start, end)) => {
+                self.replace(start, end, "");
+                true
+            }
+            None => false,
+        }
+    }
+
+    // inserts text at the cursor, replacing the selection if there is one
+    pub fn insert(&mut self, text: &str) {
+        let (start, end) = self.selection().unwrap_or((self.cursor, self.cursor));
+        self.replace(start, end, text);
+    }
+
+    // like insert, but overwrites the character on the cursor unless in
+    // insert mode
+    pub fn type_text(&mut self, text: &str) {
+        if self.insert_mode || self.selection().is_some() {
+            self.insert(text);
+        } else {
+            self.replace(self.cursor, self.cursor + 1, text);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if !self.delete_selection() && self.cursor > 0 {
+            self.replace(self.cursor - 1, self.cursor, "");
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if !self.delete_selection() {
+            self.replace(self.cursor, self.cursor + 1, "");
+        }
+    }
+
+    pub fn delete_word_back(&mut self) {
+        if !self.delete_selection() {
+            let start = word_start_before(&self.text, self.cursor);
+            self.replace(start, self.cursor, "");
+        }
+    }
+
+    pub fn delete_word_forward(&mut self) {
+        if !self.delete_selection() {
+            let end = word_end_after(&self.text, self.cursor);
+            self.replace(self.cursor, end, "");
+        }
+    }
+}
+
+#[cfg(test)]
+fn model(text: &str, cursor: usize) -> TextModel {
+    let mut model = TextModel::new();
+    model.set_text(text, cursor);
+    model
+}
+
+#[test]
+fn test_graphemes() {
+    // e + combining acute, a flag, and a family joined with ZWJs
+    let text = "e\u{301}🇳🇱👨\u{200d}👩\u{200d}👧!";
+    assert_eq!(grapheme_count(text), 4);
+    assert_eq!(slice(text, 0, 1), "e\u{301}");
+    assert_eq!(slice(text, 1, 2), "🇳🇱");
+    assert_eq!(slice(text, 2, 3), "👨\u{200d}👩\u{200d}👧");
+    assert_eq!(slice(text, 3, 99), "!");
+    assert_eq!(slice(text, 3, 1), "");
+    assert_eq!(splice(text, 1, 3, "x"), "e\u{301}x!");
+    assert_eq!(splice("ab", 5, 9, "c"), "abc");
+
+    let mut edit = model(text, 4);
+    edit.backspace();
+    edit.backspace();
+    assert_eq!(edit.text(), "e\u{301}🇳🇱");
+    edit.move_cursor(Movement::Left, false);
+    assert_eq!(edit.cursor(), 1);
+    assert_eq!(edit.on_cursor(), Some("🇳🇱"));
+    edit.delete();
+    assert_eq!(edit.text(), "e\u{301}");
+}
+
+#[test]
+fn test_masked() {
+    // one symbol per grapheme, with the cursor and selection kept
+    let mut edit = model("e\u{301}🇳🇱7", 1);
+    let masked = edit.masked("*");
+    assert_eq!(masked.text(), "***");
+    assert_eq!(
+        (
+            masked.pre_cursor(),
+            masked.on_cursor(),
+            masked.post_cursor()
+        ),
+        ("*", Some("*"), "*")
+    );
+    edit.move_cursor(Movement::End, true);
+    assert_eq!(edit.masked("*").selection(), Some((1, 3)));
+}
+
+#[test]
+fn test_combining_accent_merges() {
+    let mut edit = TextModel::new();
+    edit.toggle_insert_mode();
+    edit.type_text("e");
+    edit.type_text("\u{301}");
+    assert_eq!(edit.len(), 1);
+    assert_eq!(edit.cursor(), 1);
+    edit.type_text("x");
+    assert_eq!(edit.text(), "e\u{301}x");
+    assert_eq!(edit.cursor(), 2);
+}
+
+#[test]
+fn test_insert_and_overwrite() {
+    let mut edit = model("abc", 1);
+    assert!(!edit.insert_mode());
+    edit.type_text("X");
+    assert_eq!(edit.text(), "aXc");
+    edit.toggle_insert_mode();
+    edit.type_text("Y");
+    assert_eq!(edit.text(), "aXYc");
+    assert_eq!(edit.cursor(), 3);
+    assert_eq!(
+        (edit.pre_cursor(), edit.on_cursor(), edit.post_cursor()),
+        ("aXY", Some("c"), "")
+    );
+    edit.move_cursor(Movement::End, false);
+    assert_eq!(edit.on_cursor(), None);
+    // the cursor can't go past the end
+    edit.move_cursor(Movement::Right, false);
+    assert_eq!(edit.cursor(), 4);
+}
+
+#[test]
+fn test_word_boundaries() {
+    let text = "hello, wörld  foo_bar";
+    assert_eq!(word_start_before(text, 0), 0);
+    assert_eq!(word_start_before(text, 3), 0);
+    assert_eq!(word_start_before(text, 7), 0);
+    assert_eq!(word_start_before(text, 12), 7);
+    assert_eq!(word_start_before(text, 21), 14);
+    assert_eq!(word_start_before(text, 99), 14);
+    assert_eq!(word_end_after(text, 0), 5);
+    assert_eq!(word_end_after(text, 5), 12);
+    assert_eq!(word_end_after(text, 12), 21);
+    assert_eq!(word_end_after(text, 21), 21);
+    assert_eq!(word_end_after("", 0), 0);
+    assert_eq!(word_start_before("  ", 2), 0);
+
+    let mut edit = model(text, 12);
+    edit.delete_word_back();
+    assert_eq!(edit.text(), "hello,   foo_bar");
+    edit.delete_word_forward();
+    assert_eq!(edit.text(), "hello, ");
+    edit.move_cursor(Movement::WordLeft, false);
+    assert_eq!(edit.cursor(), 0);
+}
+
+#[test]
+fn test_selection() {
+    assert_eq!(selection_range(None, 3), None);
+    assert_eq!(selection_range(Some(3), 3), None);
+    assert_eq!(selection_range(Some(1), 4), Some((1, 4)));
+    assert_eq!(selection_range(Some(4), 1), Some((1, 4)));
+
+    let mut edit = model("wörld", 1);
+    edit.move_cursor(Movement::Right, true);
+    edit.move_cursor(Movement::Right, true);
+    edit.move_cursor(Movement::Right, true);
+    assert_eq!(edit.selected_text(), Some("örl"));
+    edit.type_text("a");
+    assert_eq!(edit.text(), "wad");
+    assert_eq!(edit.selection(), None);
+    edit.select_all();
+    assert_eq!(edit.selected_text(), Some("wad"));
+    edit.backspace();
+    assert!(edit.is_empty());
+    edit.insert("serial");
+    edit.move_cursor(Movement::Home, true);
+    edit.move_cursor(Movement::Left, false);
+    assert_eq!(edit.selection(), None);
+}
+
+#[cfg(test)]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Edit {
+        Move(Movement, bool),
+        SelectAll,
+        Type(String),
+        Insert(String),
+        Backspace,
+        Delete,
+        DeleteWordBack,
+        DeleteWordForward,
+        ToggleInsertMode,
+    }
+
+    // text with plenty of multi-codepoint graphemes in it
+    fn text() -> impl Strategy<Value = String> {
+        prop::collection::vec(
+            prop_oneof![
+                "[a-z ,]",
+                Just("e\u{301}".to_string()),
+                Just("\u{301}".to_string()),
+                Just("🇳🇱".to_string()),
+                Just("👨\u{200d}👩\u{200d}👧".to_string()),
+                any::<char>().prop_map(|c| c.to_string()),
+            ],
+            0..8,
+        )
+        .prop_map(|pieces| pieces.concat())
+    }
+
+    fn movement() -> impl Strategy<Value = Movement> {
+        prop_oneof![
+            Just(Movement::Left),
+            Just(Movement::Right),
+            Just(Movement::WordLeft),
+            Just(Movement::WordRight),
+            Just(Movement::Home),
+            Just(Movement::End),
+        ]
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            (movement(), any::<bool>()).prop_map(|(m, s)| Edit::Move(m, s)),
+            Just(Edit::SelectAll),
+            text().prop_map(Edit::Type),
+            text().prop_map(Edit::Insert),
+            Just(Edit::Backspace),
+            Just(Edit::Delete),
+            Just(Edit::DeleteWordBack),
+            Just(Edit::DeleteWordForward),
+            Just(Edit::ToggleInsertMode),
+        ]
+    }
+
+    fn apply(model: &mut TextModel, edit: &Edit) {
+        match edit {
+            Edit::Move(movement, selecting) => model.move_cursor(*movement, *selecting),
+            Edit::SelectAll => model.select_all(),
+            Edit::Type(text) => model.type_text(text),
+            Edit::Insert(text) => model.insert(text),
+            Edit::Backspace => model.backspace(),
+            Edit::Delete => model.delete(),
+            Edit::DeleteWordBack => model.delete_word_back(),
+            Edit::DeleteWordForward => model.delete_word_forward(),
+            Edit::ToggleInsertMode => model.toggle_insert_mode(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn cursor_and_selection_stay_in_range(
+            start in text(),
+            edits in prop::collection::vec(edit(), 0..24),
+        ) {
+            let mut model = TextModel::new();
+            model.set_text(&start, usize::MAX);
+            for edit in &edits {
+                apply(&mut model, edit);
+                prop_assert!(model.cursor() <= model.len());
+                if let Some((start, end)) = model.selection() {
+                    prop_assert!(start < end && end <= model.len());
+                }
+                // the pieces around the cursor always make up the text
+                let pieces = format!(
+                    "{}{}{}",
+                    model.pre_cursor(),
+                    model.on_cursor().unwrap_or(""),
+                    model.post_cursor()
+                );
+                prop_assert_eq!(pieces, model.text());
+            }
+        }
+
+        #[test]
+        fn insert_puts_text_at_the_cursor(
+            start in text(),
+            cursor in 0usize..10,
+            inserted in text(),
+        ) {
+            let mut model = TextModel::new();
+            model.set_text(&start, cursor);
+            let before = model.pre_cursor().to_string();
+            let after = slice(&start, model.cursor(), usize::MAX).to_string();
+            model.insert(&inserted);
+            prop_assert_eq!(model.text(), format!("{}{}{}", before, inserted, after));
+            prop_assert_eq!(
+                model.cursor(),
+                grapheme_count(&format!("{}{}", before, inserted))
+            );
+        }
+
+        #[test]
+        fn backspace_after_typing_restores_ascii_text(
+            start in "[a-z ]{0,10}",
+            cursor in 0usize..12,
+            typed in "[a-z]{0,10}",
+        ) {
+            let mut model = TextModel::new();
+            model.set_text(&start, cursor);
+            model.toggle_insert_mode();
+            for c in typed.chars() {
+                model.type_text(&c.to_string());
+            }
+            for _ in typed.chars() {
+                model.backspace();
+            }
+            prop_assert_eq!(model.text(), start.as_str());
+        }
+
+        #[test]
+        fn select_all_and_type_replaces_everything(start in text(), typed in "[a-z]{1,5}") {
+            let mut model = TextModel::new();
+            model.set_text(&start, 0);
+            model.select_all();
+            model.type_text(&typed);
+            prop_assert_eq!(model.text(), typed.as_str());
+            prop_assert_eq!(model.cursor(), typed.len());
+        }
+    }
+}
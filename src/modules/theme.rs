@@ -0,0 +1,365 @@
+// Themes: the style sheets of the keyboard and the application.
+//
+// A theme is a named piece of CSS. The light theme (the default) and its
+// dark counterpart are compiled in; more can be loaded from .css files and
+// are named after the file. One theme applies at a time, through a single
+// style provider for the whole screen, installed the first time `themes()`
+// is called, and it can be switched at runtime. A theme loaded from a file
+// can be hot reloaded: the file is watched and the theme applied again
+// every time it is saved, which is handy while designing one.
+//
+// CSS is checked before it is applied, so a broken style sheet is reported
+// with line and column and the theme shown before it stays in place.
+use gtk::gio;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const LIGHT: &str = "light";
+pub const DARK: &str = "dark";
+pub const LIGHT_CSS: &str = include_str!("../../resources/themes/light.css");
+pub const DARK_CSS: &str = include_str!("../../resources/themes/dark.css");
+
+// A problem found in a style sheet; lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CssError {
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for CssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(std::io::Error),
+    Css {
+        theme: String,
+        errors: Vec<CssError>,
+    },
+    UnknownTheme(String),
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThemeError::Io(e) => write!(f, "could not read theme file: {}", e),
+            ThemeError::Css { theme, errors } => {
+                write!(f, "invalid CSS in theme {:?}", theme)?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+            ThemeError::UnknownTheme(name) => write!(f, "no theme named {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ThemeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ThemeError {
+    fn from(e: std::io::Error) -> Self {
+        ThemeError::Io(e)
+    }
+}
+
+struct Theme {
+    css: String,
+    // where it was loaded from, for reloading
+    path: Option<PathBuf>,
+}
+
+struct ThemesInner {
+    provider: gtk::CssProvider,
+    themes: RefCell<BTreeMap<String, Theme>>,
+    current: RefCell<String>,
+    hot_reload: Cell<bool>,
+    // watches the current theme's file while hot reloading
+    monitor: RefCell<Option<gio::FileMonitor>>,
+}
+
+#[derive(Clone)]
+pub struct Themes {
+    inner: Rc<ThemesInner>,
+}
+
+thread_local! {
+    static THEMES: RefCell<Option<Themes>> = const { RefCell::new(None) };
+}
+
+// The themes of the default screen, installed with the light theme the
+// first time they are asked for.
+pub fn themes() -> Themes {
+    THEMES.with(|themes| {
+        themes
+            .borrow_mut()
+            .get_or_insert_with(Themes::install)
+            .clone()
+    })
+}
+
+impl Themes {
+    fn install() -> Themes {
+        let mut builtin = BTreeMap::new();
+        for (name, css) in [(LIGHT, LIGHT_CSS), (DARK, DARK_CSS)] {
+            builtin.insert(
+                name.to_string(),
+                Theme {
+                    css: css.to_string(),
+                    path: None,
+                },
+            );
+        }
+        let themes = Themes {
+            inner: Rc::new(ThemesInner {
+                provider: gtk::CssProvider::new(),
+                themes: RefCell::new(builtin),
+                current: RefCell::new(LIGHT.to_string()),
+                hot_reload: Cell::new(false),
+                monitor: RefCell::new(None),
+            }),
+        };
+        if let Err(error) = themes.apply(LIGHT, LIGHT_CSS) {
+            eprintln!("{}", error);
+        }
+        match gdk::Screen::default() {
+            Some(screen) => gtk::StyleContext::add_provider_for_screen(
+                &screen,
+                &themes.inner.provider,
+                gtk::STYLE_PROVIDER_PRIORITY_USER,
+            ),
+            None => eprintln!("no screen to apply the theme to"),
+        }
+        themes
+    }
+
+    // Collects all problems in a style sheet, rather than just the first.
+    pub fn check(name: &str, css: &str) -> Result<(), ThemeError> {
+        let provider = gtk::CssProvider::new();
+        let errors = Rc::new(RefCell::new(vec![]));
+        let errors_for_signal = Rc::clone(&errors);
+        provider.connect_parsing_error(move |_, section, error| {
+            errors_for_signal.borrow_mut().push(CssError {
+                line: section.start_line() + 1,
+                column: section.start_position() + 1,
+                message: error.to_string(),
+            });
+        });
+        let loaded = provider.load_from_data(css.as_bytes());
+        let mut errors = errors.take();
+        if let Err(error) = loaded {
+            if errors.is_empty() {
+                errors.push(CssError {
+                    line: 0,
+                    column: 0,
+                    message: error.to_string(),
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ThemeError::Css {
+                theme: name.to_string(),
+                errors,
+            })
+        }
+    }
+
+    fn apply(&self, name: &str, css: &str) -> Result<(), ThemeError> {
+        self.inner
+            .provider
+            .load_from_data(css.as_bytes())
+            .map_err(|error| ThemeError::Css {
+                theme: name.to_string(),
+                errors: vec![CssError {
+                    line: 0,
+                    column: 0,
+                    message: error.to_string(),
+                }],
+            })
+    }
+
+    // all theme names, sorted
+    pub fn names(&self) -> Vec<String> {
+        self.inner.themes.borrow().keys().cloned().collect()
+    }
+
+    pub fn current(&self) -> String {
+        self.inner.current.borrow().clone()
+    }
+
+    // Adds a theme, or replaces one of the same name (applying it again if
+    // it is the current one).
+    pub fn add(&self, name: &str, css: &str) -> Result<(), ThemeError> {
+        self.insert(name, css, None)
+    }
+
+    // Adds the theme in a .css file, named after the file, and returns the
+    // name.
+    pub fn add_file<P: AsRef<Path>>(&self, path: P) -> Result<String, ThemeError> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("")
+            .to_string();
+        let css = std::fs::read_to_string(path)?;
+        self.insert(&name, &css, Some(path.to_path_buf()))?;
+        Ok(name)
+    }
+
+    // Adds every .css file in a directory, and returns their names.
+    pub fn add_dir<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<String>, ThemeError> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "css"))
+            .collect();
+        paths.sort();
+        paths.iter().map(|path| self.add_file(path)).collect()
+    }
+
+    fn insert(&self, name: &str, css: &str, path: Option<PathBuf>) -> Result<(), ThemeError> {
+        Self::check(name, css)?;
+        self.inner.themes.borrow_mut().insert(
+            name.to_string(),
+            Theme {
+                css: css.to_string(),
+                path,
+            },
+        );
+        if name == self.current() {
+            self.apply(name, css)?;
+            self.watch();
+        }
+        Ok(())
+    }
+
+    pub fn set_theme(&self, name: &str) -> Result<(), ThemeError> {
+        let css = match self.inner.themes.borrow().get(name) {
+            Some(theme) => theme.css.clone(),
+            None => return Err(ThemeError::UnknownTheme(name.to_string())),
+        };
+        // only once it applied, so a failure leaves the old theme current
+        self.apply(name, &css)?;
+        *self.inner.current.borrow_mut() = name.to_string();
+        self.watch();
+        Ok(())
+    }
+
+    // Reads the current theme's file again, if it has one. Broken CSS is
+    // reported and leaves the theme as it was.
+    pub fn reload(&self) -> Result<(), ThemeError> {
+        let name = self.current();
+        let path = self
+            .inner
+            .themes
+            .borrow()
+            .get(&name)
+            .and_then(|theme| theme.path.clone());
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let css = std::fs::read_to_string(&path)?;
+        Self::check(&name, &css)?;
+        // still watched, so just the CSS changes
+        if let Some(theme) = self.inner.themes.borrow_mut().get_mut(&name) {
+            theme.css = css.clone();
+        }
+        self.apply(&name, &css)
+    }
+
+    // While on, the current theme is reloaded whenever its file changes;
+    // problems are printed, since nobody is waiting for them.
+    pub fn set_hot_reload(&self, enabled: bool) {
+        self.inner.hot_reload.set(enabled);
+        self.watch();
+    }
+
+    fn watch(&self) {
+        let path = self
+            .inner
+            .themes
+            .borrow()
+            .get(&self.current())
+            .and_then(|theme| theme.path.clone());
+        let watched = match (self.inner.hot_reload.get(), path) {
+            (true, Some(path)) => path,
+            _ => {
+                // dropping the monitor stops it
+                *self.inner.monitor.borrow_mut() = None;
+                return;
+            }
+        };
+        let file = gio::File::for_path(&watched);
+        let monitor = match file.monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE) {
+            Ok(monitor) => monitor,
+            Err(error) => {
+                eprintln!("can't watch {:?}: {}", watched, error);
+                return;
+            }
+        };
+        let weak_inner = Rc::downgrade(&self.inner);
+        monitor.connect_changed(move |_, _, _, event| {
+            // saving may take several writes; this comes after the last
+            if event != gio::FileMonitorEvent::ChangesDoneHint {
+                return;
+            }
+            if let Some(inner) = weak_inner.upgrade() {
+                if let Err(error) = (Themes { inner }).reload() {
+                    eprintln!("{}", error);
+                }
+            }
+        });
+        *self.inner.monitor.borrow_mut() = Some(monitor);
+    }
+}
+
+#[test]
+fn test_builtin_themes_match() {
+    // the selectors of a style sheet, in order, after its opening comment
+    fn selectors(css: &str) -> Vec<String> {
+        let rules = css.split_once("*/").map_or(css, |(_, rules)| rules);
+        rules.split('}')
+            .filter_map(|rule| rule.split_once('{'))
+            .map(|(selector, _)| selector.trim().to_string())
+            .collect()
+    }
+    // the dark theme styles everything the light one does
+    let light = selectors(LIGHT_CSS);
+    assert_eq!(light, selectors(DARK_CSS));
+    // including the state the keyboard shows through style classes
+    for class in [
+        "keyboard_button_disabled",
+        "shift_oneshot",
+        "shift_locked",
+        "symbols_active",
+        "layer_active",
+        "alternate_selected",
+        "compose_pending",
+        "scan_highlight",
+        "key_focus",
+    ] {
+        assert!(light.contains(&format!(".{}", class)), "{}", class);
+    }
+}
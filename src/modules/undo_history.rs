@@ -0,0 +1,168 @@
+// Undo/redo history for the virtual keyboard input.
+//
+// The history keeps snapshots of the text and cursor position from before
+// each edit. A run of typed characters counts as a single edit, so undo
+// takes back a whole word (or sentence) rather than one letter at a time;
+// any other edit, or moving the cursor, starts a new step.
+use std::collections::VecDeque;
+
+// the oldest steps are dropped beyond this
+pub const UNDO_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub text: String,
+    pub cursor: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    // a character typed at the cursor
+    Insert,
+    // anything else: deletions, pastes, replacing a selection, ...
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoHistory {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    limit: usize,
+    // whether the last step is a run of inserts that may still grow
+    coalescing: bool,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoHistory {
+    pub fn new() -> UndoHistory {
+        Self::with_limit(UNDO_LIMIT)
+    }
+
+    pub fn with_limit(limit: usize) -> UndoHistory {
+        UndoHistory {
+            undo: VecDeque::new(),
+            redo: vec![],
+            limit,
+            coalescing: false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.coalescing = false;
+    }
+
+    // to be called with the state from before an edit
+    pub fn record(&mut self, before: Snapshot, kind: EditKind) {
+        self.redo.clear();
+        if kind == EditKind::Insert && self.coalescing {
+            // part of the same run of typing; the step already has its start
+            return;
+        }
+        self.undo.push_back(before);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+        self.coalescing = kind == EditKind::Insert;
+    }
+
+    // to be called when the cursor moves without editing, so that typing
+    // somewhere else becomes a step of its own
+    pub fn break_coalescing(&mut self) {
+        self.coalescing = false;
+    }
+
+    // returns the state to go back to, given the current one
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(current);
+        self.coalescing = false;
+        Some(previous)
+    }
+
+    // returns the state to go forward to, given the current one
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(current);
+        self.coalescing = false;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+fn snapshot(text: &str, cursor: usize) -> Snapshot {
+    Snapshot {
+        text: text.to_string(),
+        cursor,
+    }
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut history = UndoHistory::new();
+    assert!(!history.can_undo());
+    assert_eq!(history.undo(snapshot("", 0)), None);
+    history.record(snapshot("abc", 3), EditKind::Other);
+    history.record(snapshot("ab", 2), EditKind::Other);
+    assert_eq!(history.undo(snapshot("a", 1)), Some(snapshot("ab", 2)));
+    assert_eq!(history.undo(snapshot("ab", 2)), Some(snapshot("abc", 3)));
+    assert!(!history.can_undo());
+    assert_eq!(history.redo(snapshot("abc", 3)), Some(snapshot("ab", 2)));
+    assert_eq!(history.redo(snapshot("ab", 2)), Some(snapshot("a", 1)));
+    assert!(!history.can_redo());
+    // a new edit after undoing forgets what could be redone
+    history.undo(snapshot("a", 1));
+    history.record(snapshot("ab", 2), EditKind::Other);
+    assert!(!history.can_redo());
+}
+
+#[test]
+fn test_inserts_coalesce() {
+    let mut history = UndoHistory::new();
+    history.record(snapshot("", 0), EditKind::Insert);
+    history.record(snapshot("h", 1), EditKind::Insert);
+    history.record(snapshot("hi", 2), EditKind::Insert);
+    // a deletion is a step of its own, and so is the typing after it
+    history.record(snapshot("hi!", 3), EditKind::Other);
+    history.record(snapshot("hi", 2), EditKind::Insert);
+    history.record(snapshot("hi?", 3), EditKind::Insert);
+    assert_eq!(history.undo(snapshot("hi?!", 4)), Some(snapshot("hi", 2)));
+    assert_eq!(history.undo(snapshot("hi", 2)), Some(snapshot("hi!", 3)));
+    assert_eq!(history.undo(snapshot("hi!", 3)), Some(snapshot("", 0)));
+    assert!(!history.can_undo());
+    // moving the cursor ends a run of typing
+    history.clear();
+    history.record(snapshot("", 0), EditKind::Insert);
+    history.break_coalescing();
+    history.record(snapshot("a", 0), EditKind::Insert);
+    assert_eq!(history.undo(snapshot("ba", 1)), Some(snapshot("a", 0)));
+}
+
+#[test]
+fn test_undo_limit() {
+    let mut history = UndoHistory::with_limit(3);
+    for n in 0..5 {
+        history.record(snapshot(&"x".repeat(n), n), EditKind::Other);
+    }
+    let mut restored = vec![];
+    let mut current = snapshot("xxxxx", 5);
+    while let Some(previous) = history.undo(current.clone()) {
+        restored.push(previous.cursor);
+        current = previous;
+    }
+    assert_eq!(restored, [4, 3, 2]);
+}
@@ -1,15 +1,33 @@
-use crate::modules::home_screen::SharedData;
+use crate::modules::accessibility;
+use crate::modules::compose::{ComposeInput, ComposeOutput, Composer};
+use crate::modules::key_focus::{FocusDirection, FocusOptions, KeyFocus, KeySpan};
+use crate::modules::keyboard_layout::{
+    InputPurpose, KeyboardLayout, LayoutError, LayoutOrientation,
+};
+use crate::modules::modal::{modal, Modal};
+use crate::modules::modifier_state::{ModifierState, ShiftState};
+use crate::modules::navigation::Screen;
+use crate::modules::scanning::{Highlight, ScanOptions, Scanner};
+use crate::modules::text_model::{self, Movement, TextModel};
+use crate::modules::theme;
+use crate::modules::undo_history::{EditKind, Snapshot, UndoHistory};
 extern crate gtk;
 use glib;
 use gtk::prelude::*;
-use gtk::{Button, Label, CssProvider};
-
-use std::sync::Arc;
-use std::sync::Mutex;
+use gtk::subclass::prelude::*;
+use gtk::{Button, Label};
 
+// The panel the CSS font sizes were chosen for; on other sizes the fonts are
+// scaled to match.
 pub const SCREEN_WIDTH: i32 = 800;
 pub const BORDER_WIDTH: i32 = 4;
 pub const SCREEN_HEIGHT: i32 = 480;
+// shares of the keyboard's height for the prompt, the input and the keys
+const PROMPT_SHARE: i32 = 1;
+const SCREEN_SHARE: i32 = 1;
+const KEYS_SHARE: i32 = 6;
+// fonts never shrink below this, however small the keyboard
+const MIN_FONT_SCALE: f64 = 0.5;
 pub const SYMBOL_ENTER: &str = "✔";
 pub const ID_ENTER: &str = "ok";
 pub const SYMBOL_CANCEL: &str = "🗙";
@@ -24,929 +42,797 @@ pub const SYMBOL_INSERT: &str = "Ins";
 pub const ID_INSERT: &str = "insert";
 pub const SYMBOL_DELETE: &str = "Del";
 pub const ID_DELETE: &str = "delete";
+pub const SYMBOL_HOME: &str = "⇤";
+pub const ID_HOME: &str = "home";
+pub const SYMBOL_END: &str = "⇥";
+pub const ID_END: &str = "end";
+pub const SYMBOL_WORD_LEFT: &str = "◁◁";
+pub const ID_WORD_LEFT: &str = "word_left";
+pub const SYMBOL_WORD_RIGHT: &str = "▷▷";
+pub const ID_WORD_RIGHT: &str = "word_right";
+pub const SYMBOL_DELETE_WORD_BACK: &str = "⌫⌫";
+pub const ID_DELETE_WORD_BACK: &str = "delete_word_back";
+pub const SYMBOL_DELETE_WORD_FORWARD: &str = "Del²";
+pub const ID_DELETE_WORD_FORWARD: &str = "delete_word_forward";
+pub const SYMBOL_CLEAR: &str = "⎚";
+pub const ID_CLEAR: &str = "clear";
+pub const SYMBOL_SELECT_LEFT: &str = "⇧◁";
+pub const ID_SELECT_LEFT: &str = "select_left";
+pub const SYMBOL_SELECT_RIGHT: &str = "⇧▷";
+pub const ID_SELECT_RIGHT: &str = "select_right";
+pub const SYMBOL_SELECT_HOME: &str = "⇧⇤";
+pub const ID_SELECT_HOME: &str = "select_home";
+pub const SYMBOL_SELECT_END: &str = "⇧⇥";
+pub const ID_SELECT_END: &str = "select_end";
+pub const SYMBOL_SELECT_ALL: &str = "⬚";
+pub const ID_SELECT_ALL: &str = "select_all";
+pub const SYMBOL_COPY: &str = "⎘";
+pub const ID_COPY: &str = "copy";
+pub const SYMBOL_CUT: &str = "✂";
+pub const ID_CUT: &str = "cut";
+pub const SYMBOL_PASTE: &str = "📋";
+pub const ID_PASTE: &str = "paste";
+pub const SYMBOL_UNDO: &str = "↶";
+pub const ID_UNDO: &str = "undo";
+pub const SYMBOL_REDO: &str = "↷";
+pub const ID_REDO: &str = "redo";
 pub const SYMBOL_SHIFT: &str = "⇧";
 pub const ID_SHIFT: &str = "shift";
+pub const SYMBOL_SYMBOLS: &str = "?123";
+pub const ID_SYMBOLS: &str = "symbols";
+pub const SYMBOL_COMPOSE: &str = "⎄";
+pub const ID_COMPOSE: &str = "compose";
+// dead keys are labelled with their accent, e.g. "´"
+pub const ID_DEAD: &str = "dead";
+pub const SYMBOL_LAYOUT: &str = "🌐";
+pub const ID_LAYOUT: &str = "layout";
+// "layer:" and a layer's name, e.g. "layer:extra", switches to that layer
+// and back
+pub const ID_LAYER_PREFIX: &str = "layer:";
 pub const ID_DISABLED: &str = "disabled";
-pub const VIRTUAL_KEYBOARD_CSS: &str = ".keyboard_button { margin:0; padding:0; font-family: Verdana; border-radius:0; border: 1px solid #999999; font-size: 26px; font-weight: bold; } \
-            .keyboard_button_disabled { color: #CCCCCC; margin:0; padding:0; font-family: Verdana; border-radius:0; border: 1px solid #999999; font-size: 26px; font-weight: bold; } \
-            .keyboard_button_row { padding:0; margin: 0; border:0; background: #cccccc; } \
-            .root { padding:0; margin: 0; border:0; background: #cccccc; } \
-            #ok { color: #009900; } \
-            #cancel { color: #ff0000; } \
-            #delete { font-family: Verdana; font-size: 12px; font-weight: normal; color: #000000; } \
-            #insert { font-family: Verdana; font-size: 12px; font-weight: normal; } \
-            .insert_active { color: #ff0000; } \
-            .insert_inactive { color: #000000; } \
-            #screen { font-family: 'Monospace';background: #eeeeee; font-size: 30px; font-weight: bold; } \
-            #prompt { font-family: 'Verdana'; font-size: 30px; font-weight: bold; background: #cccccc; color: #000000;} \
-            ";
+pub const SYMBOL_MASK: &str = "●";
+// how long the cursor stays on, and then off
+pub const CURSOR_BLINK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(400);
+// how long a key must be held before its alternates pop up
+pub const LONG_PRESS_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
 //"↵";
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DialogResult {
-    Ok,
-    Cancel,
+    // the text that was entered
+    Ok(String),
+    Cancelled,
 }
 
-type DialogCloseAction = fn(&std::sync::MutexGuard<'_, SharedData>, DialogResult);
-/*struct SharedData {
-    home_screen: Mutex<Option<HomeScreen>>,
-    virtual_keyboard: Option<VirtualKeyboard>,
+// Called once when the keyboard closes. It runs from the main loop after the
+// key handler has returned, so nothing is borrowed and it is free to open
+// another screen (or the keyboard again).
+pub type DialogCallback = Box<dyn FnOnce(DialogResult)>;
+
+#[derive(Debug, Clone, Default)]
+pub struct PromptOptions {
+    // text to start editing from, e.g. the current value
+    pub text: String,
 }
-impl SharedData {
-    fn new() -> SharedData {
-        SharedData {
-            home_screen: Mutex::new(None),
-            virtual_keyboard: None,
-        }
+
+mod imp;
+
+glib::wrapper! {
+    // The on-screen keyboard, with the prompt and the text being edited
+    // above the keys. It is a GtkBox, so it can be packed, shown and hidden
+    // (and created from a .ui file) like any other widget.
+    //
+    // Properties: "text", "cursor-position", "prompt", "accept" and
+    // "insert-mode". Signals: "changed" when the text is edited, "activate"
+    // on OK and "cancelled" on cancel.
+    pub struct VirtualKeyboard(ObjectSubclass<imp::VirtualKeyboard>)
+        @extends gtk::Box, gtk::Container, gtk::Widget,
+        @implements gtk::Buildable, gtk::Orientable;
+}
+
+fn dead_key_accent(keyval: u32) -> Option<char> {
+    // the spacing accent that goes with a dead key keyval
+    let accents = [
+        (*gdk::keys::constants::dead_grave, '`'),
+        (*gdk::keys::constants::dead_acute, '´'),
+        (*gdk::keys::constants::dead_circumflex, '^'),
+        (*gdk::keys::constants::dead_tilde, '~'),
+        (*gdk::keys::constants::dead_diaeresis, '¨'),
+        (*gdk::keys::constants::dead_abovering, '˚'),
+        (*gdk::keys::constants::dead_cedilla, '¸'),
+        (*gdk::keys::constants::dead_caron, 'ˇ'),
+    ];
+    accents
+        .iter()
+        .find(|(dead_keyval, _)| *dead_keyval == keyval)
+        .map(|(_, accent)| *accent)
+}
+
+// What a physical key does for key focus navigation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusKey {
+    Toggle,
+    Move(FocusDirection),
+    Press,
+}
+
+// The toggle key, or the keys that move and press while in grid focus mode,
+// given whether that mode is on. Returns None for the keys that type and
+// edit as usual, the arrows and Enter among them while not in it.
+pub fn focus_key(keyval: u32, options: &FocusOptions, key_focus_mode: bool) -> Option<FocusKey> {
+    if keyval == options.toggle_key {
+        return Some(FocusKey::Toggle);
+    }
+    if !key_focus_mode {
+        return None;
     }
+    let direction = if keyval == *gdk::keys::constants::Left
+        || keyval == *gdk::keys::constants::KP_Left
+    {
+        FocusDirection::Left
+    } else if keyval == *gdk::keys::constants::Right || keyval == *gdk::keys::constants::KP_Right {
+        FocusDirection::Right
+    } else if keyval == *gdk::keys::constants::Up || keyval == *gdk::keys::constants::KP_Up {
+        FocusDirection::Up
+    } else if keyval == *gdk::keys::constants::Down || keyval == *gdk::keys::constants::KP_Down {
+        FocusDirection::Down
+    } else if keyval == options.next_key {
+        FocusDirection::Next
+    } else if keyval == options.previous_key {
+        FocusDirection::Previous
+    } else if keyval == *gdk::keys::constants::Return || keyval == *gdk::keys::constants::KP_Enter {
+        return Some(FocusKey::Press);
+    } else {
+        return None;
+    };
+    Some(FocusKey::Move(direction))
 }
-*/
-pub struct VirtualKeyboard {
-    pub widget: gtk::Box,
-    pub input: Mutex<String>,
-    close_action: Mutex<DialogCloseAction>,
-    prompt: Label,
-    screen: Label,
-    active_key_layer: Mutex<usize>,
-    keys_layers: Vec<gtk::Box>,
-    cursor_state: Mutex<bool>,
-    insert_mode: Mutex<bool>,
-    cursor_pos: Mutex<usize>,
-    pub accept: String,
+
+// focus_key, for a key pressed with the modifiers in `state`
+fn translate_focus_key(
+    keyval: u32,
+    state: gdk::ModifierType,
+    options: &FocusOptions,
+    key_focus_mode: bool,
+) -> Option<FocusKey> {
+    // with a modifier held, arrows and Enter keep their editing meaning
+    if state.intersects(
+        gdk::ModifierType::SHIFT_MASK
+            | gdk::ModifierType::CONTROL_MASK
+            | gdk::ModifierType::MOD1_MASK,
+    ) {
+        return None;
+    }
+    focus_key(keyval, options, key_focus_mode)
+}
+
+fn handle_focus_key(keyboard: &VirtualKeyboard, focus_key: FocusKey) {
+    match focus_key {
+        FocusKey::Toggle => keyboard.set_key_focus_mode(!keyboard.is_key_focus_mode()),
+        FocusKey::Move(direction) => keyboard.move_key_focus(direction),
+        FocusKey::Press => keyboard.press_focused_key(),
+    }
 }
-// key width, special key name, labels
+
+// Translates a key press on a physical keyboard into the (plain key, special
+// key name) pair that an on-screen key press would give. Returns None for
+// keys that the virtual keyboard should ignore.
+pub fn translate_key_event(
+    event: &gdk::EventKey,
+    purpose: InputPurpose,
+    accept: &str,
+) -> Option<(String, String)> {
+    translate_key(event.keyval(), event.state(), purpose, accept)
+}
+
+// translate_key_event, for a key pressed with the modifiers in `state`
+pub fn translate_key(
+    key: gdk::keys::Key,
+    state: gdk::ModifierType,
+    purpose: InputPurpose,
+    accept: &str,
+) -> Option<(String, String)> {
+    let keyval: u32 = *key;
+    // Ctrl turns the movement and deletion keys into their word-wise versions,
+    // Shift makes the movement keys extend the selection
+    let control = state.contains(gdk::ModifierType::CONTROL_MASK);
+    let shift = state.contains(gdk::ModifierType::SHIFT_MASK);
+    let shortcut_key = if control {
+        match key.to_lower().to_unicode() {
+            Some('a') => Some(ID_SELECT_ALL),
+            Some('c') => Some(ID_COPY),
+            Some('x') => Some(ID_CUT),
+            Some('v') => Some(ID_PASTE),
+            Some('z') if shift => Some(ID_REDO),
+            Some('z') => Some(ID_UNDO),
+            Some('y') => Some(ID_REDO),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    let special_key = if shortcut_key.is_some() {
+        shortcut_key
+    } else if keyval == *gdk::keys::constants::BackSpace {
+        Some(if control {
+            ID_DELETE_WORD_BACK
+        } else {
+            ID_BACKSPACE
+        })
+    } else if keyval == *gdk::keys::constants::Delete || keyval == *gdk::keys::constants::KP_Delete
+    {
+        Some(if control {
+            ID_DELETE_WORD_FORWARD
+        } else {
+            ID_DELETE
+        })
+    } else if keyval == *gdk::keys::constants::Insert {
+        Some(ID_INSERT)
+    } else if keyval == *gdk::keys::constants::Left || keyval == *gdk::keys::constants::KP_Left {
+        Some(if control {
+            ID_WORD_LEFT
+        } else if shift {
+            ID_SELECT_LEFT
+        } else {
+            ID_LEFT
+        })
+    } else if keyval == *gdk::keys::constants::Right || keyval == *gdk::keys::constants::KP_Right {
+        Some(if control {
+            ID_WORD_RIGHT
+        } else if shift {
+            ID_SELECT_RIGHT
+        } else {
+            ID_RIGHT
+        })
+    } else if keyval == *gdk::keys::constants::Home || keyval == *gdk::keys::constants::KP_Home {
+        Some(if shift { ID_SELECT_HOME } else { ID_HOME })
+    } else if keyval == *gdk::keys::constants::End || keyval == *gdk::keys::constants::KP_End {
+        Some(if shift { ID_SELECT_END } else { ID_END })
+    } else if keyval == *gdk::keys::constants::Return || keyval == *gdk::keys::constants::KP_Enter {
+        Some(ID_ENTER)
+    } else if keyval == *gdk::keys::constants::Escape {
+        Some(ID_CANCEL)
+    } else if keyval == *gdk::keys::constants::Multi_key {
+        Some(ID_COMPOSE)
+    } else {
+        None
+    };
+    if let Some(special_key) = special_key {
+        return Some(("".to_string(), special_key.to_string()));
+    }
+    if let Some(accent) = dead_key_accent(keyval) {
+        return Some((accent.to_string(), ID_DEAD.to_string()));
+    }
+    // Ctrl and Alt combinations are shortcuts, not text. AltGr (for €, @
+    // and friends on many layouts) is a different modifier and still types.
+    if state.intersects(gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::MOD1_MASK) {
+        return None;
+    }
+    let character = key.to_unicode()?;
+    if character.is_control() {
+        return None;
+    }
+    let key = if purpose == InputPurpose::Hex {
+        // the hex keypad only has upper case digits
+        character.to_uppercase().to_string()
+    } else {
+        character.to_string()
+    };
+    if !accept.is_empty() && !accept.contains(&key) {
+        return None;
+    }
+    Some((key, "".to_string()))
+}
+
 pub fn physical_keyboard_handler(
-    shareddata_for_keypress: &Arc<Mutex<SharedData>>,
+    keyboard: &VirtualKeyboard,
     values: &[glib::Value],
 ) -> Option<glib::Value> {
-    let sd = shareddata_for_keypress.lock().expect("poison");
-    let vk = sd.virtual_keyboard.as_ref();
-    if let Some(keyboard) = vk {
-        println!("keypress");
-        let raw_event = &values[1].get::<gdk::Event>().unwrap();
-        // You have to cast to the correct event type to access some of the fields
-        match raw_event.downcast_ref::<gdk::EventKey>() {
-            Some(event) => {
-                let keyval: u32 = *event.keyval();
-                // let state: gdk::ModifierType = event.state();
-                let (plain_key, special_key) = if keyval <= 255 {
-                    let character = char::from(keyval as u8);
-                    let key = character.to_string();
-                    if keyboard.accept != "" {
-                        if !keyboard.accept.contains(&key) {
-                            ("".to_string(), "".to_string())
-                        } else {
-                            (key, "".to_string())
-                        }
-                    } else {
-                        (key, "".to_string())
-                    }
-                } else {
-                    if keyval == *gdk::keys::constants::BackSpace {
-                        ("".to_string(), ID_BACKSPACE.to_string())
-                    } else if keyval == *gdk::keys::constants::Delete {
-                        ("".to_string(), ID_DELETE.to_string())
-                    } else if keyval == *gdk::keys::constants::Insert {
-                        ("".to_string(), ID_INSERT.to_string())
-                    } else if keyval == *gdk::keys::constants::Left {
-                        ("".to_string(), ID_LEFT.to_string())
-                    } else if keyval == *gdk::keys::constants::Right {
-                        ("".to_string(), ID_RIGHT.to_string())
-                    } else if keyval == *gdk::keys::constants::Return {
-                        ("".to_string(), ID_ENTER.to_string())
-                    } else if keyval == *gdk::keys::constants::Escape {
-                        ("".to_string(), ID_CANCEL.to_string())
-                    } else {
-                        ("".to_string(), "".to_string())
-                    }
-                };
-                if format!("{}{}", plain_key, special_key) != "" {
-                    keyboard.handle_key(&sd, &plain_key, &special_key);
-                }
-            }
-            None => {}
+    // while the keyboard isn't shown, keys belong to whatever is
+    if !keyboard.is_visible() {
+        return Some(false.to_value());
+    }
+    let mut handled = false;
+    let raw_event = &values[1].get::<gdk::Event>().unwrap();
+    // You have to cast to the correct event type to access some of the fields
+    if let Some(event) = raw_event.downcast_ref::<gdk::EventKey>() {
+        if keyboard.is_scanning() && *event.keyval() == keyboard.scan_options().switch_key {
+            // the switch of switch access
+            keyboard.scan_switch();
+            handled = true;
+        } else if let Some(focus_key) = translate_focus_key(
+            *event.keyval(),
+            event.state(),
+            &keyboard.focus_options(),
+            keyboard.is_key_focus_mode(),
+        ) {
+            handle_focus_key(keyboard, focus_key);
+            handled = true;
+        } else if let Some((plain_key, special_key)) =
+            translate_key_event(event, keyboard.purpose(), &keyboard.accept())
+        {
+            keyboard.handle_key(&plain_key, &special_key);
+            handled = true;
         }
-    };
-    Some(true.into())
+    }
+    Some(handled.to_value())
 }
 
-type KeyDef = (f32, String, [String; 3]);
 impl VirtualKeyboard {
-    pub fn charlen(input: &str) -> usize {
-        let mut result_len = 0;
-        for (l, _c) in input.chars().enumerate() {
-            result_len = l + 1;
-        }
-        return result_len;
-    }
-
-    pub fn pre_cursor(input: &str, cursor_pos: usize) -> Option<String> {
-        // given a string and a cursor position,
-        // returns the portion of the string before the cursor
-        let result = if Self::charlen(input) > cursor_pos {
-            let mut tempstring = "".to_string();
-            for (l, c) in input.chars().enumerate() {
-                if l == cursor_pos {
-                    return Some(tempstring);
-                }
-                tempstring.push(c);
-            }
-            return Some("".to_string());
-            //Some(input[..cursor_pos].to_string())
-        } else {
-            if input.to_string() == "" {
-                None
-            } else {
-                Some(input.to_string())
-            }
-        };
-        result
-    }
-    pub fn on_cursor(input: &str, cursor_pos: usize) -> Option<String> {
-        // given a string and a cursor position,
-        // returns the portion of the string on the cursor
-        let mut temp_string = "".to_string();
-        let result = if cursor_pos < Self::charlen(input) {
-            temp_string.push(input.chars().nth(cursor_pos)?);
-            return Some(temp_string);
+    fn escape(text: &str) -> String {
+        // the input is kept as typed; only the markup built from it is escaped
+        glib::markup_escape_text(text).to_string()
+    }
+    pub fn markup(model: &TextModel, masked: bool, cursor_shown: bool) -> String {
+        // Pango markup for the input: the text with the selection or the
+        // cursor block highlighted
+        let masked_model: TextModel;
+        let model = if masked {
+            // show one mask symbol per character, e.g. for PIN entry
+            masked_model = model.masked(SYMBOL_MASK);
+            &masked_model
         } else {
-            None
+            model
         };
-        result
-    }
-    pub fn post_cursor(input: &str, cursor_pos: usize) -> Option<String> {
-        // given a string and a cursor position,
-        // returns the portion of the string after the cursor
-        let l = Self::charlen(input);
-        if l > cursor_pos + 1 {
-            let mut tempstring = "".to_string();
-            for i in (cursor_pos + 1)..l {
-                tempstring.push(input.chars().nth(i)?);
-            }
-            return Some(tempstring);
-        } else {
-            None
-        }
-    }
-    fn update_label(&self, cursor: Option<&str>) {
-        let cursorshape = if let Some(c) = cursor { c } else { "_" };
-        let input: &str = &self.input.lock().expect("poison");
-        //let mut cursor_pos = input.len(); // but can be anything from 0..input.len() for edits
-        let cursor_pos: usize = *self.cursor_pos.lock().expect("poison");
-        /* This IF shows that we can have a cursor underneath existing text
-           if cursor_pos >= 3 {
-               cursor_pos = 3;
-           }
-        */
-        let csh = if cursorshape == "_" {
-            let insertmode: bool = { *self.insert_mode.lock().expect("poison") };
+        let input = model.text();
+        if let Some((start, end)) = model.selection() {
+            // the selection replaces the cursor block while there is one
+            format!(
+                "{}<span foreground=\"white\" background=\"#3465a4\">{}</span>{}",
+                Self::escape(text_model::slice(input, 0, start)),
+                Self::escape(text_model::slice(input, start, end)),
+                Self::escape(text_model::slice(input, end, usize::MAX)),
+            )
+        } else if cursor_shown {
             // markup is not html but "Pango"
-            let cursor_decoration_pre: &str = if insertmode {
+            let cursor_decoration_pre: &str = if model.insert_mode() {
                 "<span foreground=\"white\" background=\"black\">"
             } else {
                 "<u>"
             };
-            let cursor_decoration_post: &str = if insertmode { "</span>" } else { "</u>" };
+            let cursor_decoration_post: &str = if model.insert_mode() {
+                "</span>"
+            } else {
+                "</u>"
+            };
             format!(
                 "{}{}{}{}{}",
-                Self::pre_cursor(input, cursor_pos)
-                    .unwrap_or("".to_string())
-                    .replace("<", "&lt;"),
+                Self::escape(model.pre_cursor()),
                 cursor_decoration_pre,
-                Self::on_cursor(input, cursor_pos)
-                    .unwrap_or(" ".to_string())
-                    .replace("<", "&lt;"),
+                Self::escape(model.on_cursor().unwrap_or(" ")),
                 cursor_decoration_post,
-                Self::post_cursor(input, cursor_pos)
-                    .unwrap_or("".to_string())
-                    .replace("<", "&lt;"),
+                Self::escape(model.post_cursor()),
             )
         } else {
-            let filler = if Self::on_cursor(input, cursor_pos).is_none() {
-                " "
-            } else {
-                ""
-            }
-            .to_string();
-            format!("{}{}", input.to_string().replace("<", "&lt;"), filler)
-        };
-        self.screen.set_markup(&csh);
-    }
-    fn blink_cursor(shared_data: &Arc<Mutex<SharedData>>) {
-        let sd = shared_data.lock().expect("poison");
-        let virtual_keyboard = sd.virtual_keyboard.as_ref();
-        if let Some(vk) = virtual_keyboard {
-            let cs = *vk.cursor_state.lock().expect("poison");
-            {
-                *vk.cursor_state.lock().expect("poison") = !cs;
-            }
-            vk.update_label(Some(if cs { "_" } else { " " }));
+            let filler = if model.on_cursor().is_none() { " " } else { "" };
+            format!("{}{}", Self::escape(input), filler)
         }
     }
 
-    fn append_input(&self, input: &str) {
-        {
-            let cursor_pos: usize = *self.cursor_pos.lock().expect("poison");
-            let mut input_field = self.input.lock().expect("poison");
-            let insertmode: bool = { *self.insert_mode.lock().expect("poison") };
-            let pre = Self::pre_cursor(&input_field, cursor_pos)
-                .unwrap_or("".to_string())
-                .replace("<", "&lt;");
-            let onc = Self::on_cursor(&input_field, cursor_pos)
-                .unwrap_or(" ".to_string())
-                .replace("<", "&lt;");
-            let post = Self::post_cursor(&input_field, cursor_pos)
-                .unwrap_or("".to_string())
-                .replace("<", "&lt;");
-
-            let new_input = if insertmode {
-                format!("{}{}{}{}", pre, input, onc, post)
-            } else {
-                format!("{}{}{}", pre, input, post)
-            };
-            *input_field = new_input;
-        }
-        {
-            let mut cursorpos = self.cursor_pos.lock().expect("poison");
-            *cursorpos += 1;
+    // For any purpose other than FreeForm the keyboard shows a dedicated
+    // keypad; an empty `accept` then defaults to the purpose's characters.
+    pub fn new(prompt_text: &str, accept: &str, purpose: InputPurpose) -> VirtualKeyboard {
+        match KeyboardLayout::keypad(purpose) {
+            Some(keypad) => {
+                let accept = if accept.is_empty() {
+                    purpose.accept()
+                } else {
+                    accept
+                };
+                Self::_new(prompt_text, accept, vec![keypad], purpose)
+            }
+            None => Self::with_layout(prompt_text, accept, &KeyboardLayout::builtin()),
         }
-        self.update_label(None);
     }
 
-    fn del_input(&self) {
-        {
-            let cursor_pos: usize = *self.cursor_pos.lock().expect("poison");
-            let mut input_field = self.input.lock().expect("poison");
-            let pre = Self::pre_cursor(&input_field, cursor_pos)
-                .unwrap_or("".to_string())
-                .replace("<", "&lt;");
-            let post = Self::post_cursor(&input_field, cursor_pos)
-                .unwrap_or("".to_string())
-                .replace("<", "&lt;");
-
-            let new_input = { format!("{}{}", pre, post) };
-            *input_field = new_input;
-        }
-        self.update_label(None);
+    pub fn with_layout(
+        prompt_text: &str,
+        accept: &str,
+        layout: &KeyboardLayout,
+    ) -> VirtualKeyboard {
+        Self::with_layouts(prompt_text, accept, vec![layout.clone()])
     }
 
-    fn move_cursor_left(&self) {
-        {
-            let mut cursorpos = self.cursor_pos.lock().expect("poison");
-            if *cursorpos > 0 {
-                *cursorpos -= 1;
-            }
+    // The layout key cycles through `layouts` in order; the first one is
+    // active initially. An empty list falls back to the built-in layout.
+    pub fn with_layouts(
+        prompt_text: &str,
+        accept: &str,
+        mut layouts: Vec<KeyboardLayout>,
+    ) -> VirtualKeyboard {
+        if layouts.is_empty() {
+            layouts.push(KeyboardLayout::builtin());
         }
-        self.update_label(None); // to keep curor visible while moving it
+        Self::_new(prompt_text, accept, layouts, InputPurpose::FreeForm)
     }
 
-    fn move_cursor_right(&self) {
-        {
-            let input_field = self.input.lock().expect("poison");
-            let mut cursorpos = self.cursor_pos.lock().expect("poison");
-            if *cursorpos <= Self::charlen(&input_field) {
-                *cursorpos += 1;
-            }
-        }
-        self.update_label(None); // to keep curor visible while moving it
+    fn _new(
+        prompt_text: &str,
+        accept: &str,
+        layouts: Vec<KeyboardLayout>,
+        purpose: InputPurpose,
+    ) -> VirtualKeyboard {
+        let keyboard: VirtualKeyboard = glib::Object::builder()
+            .property("prompt", prompt_text)
+            .build();
+        keyboard.imp().configure(layouts, purpose, accept);
+        keyboard
     }
 
-    fn backspace(&self) {
-        // This code is pretty ugly and inefficient (not that that matters for
-        // single-line strings of reasonable finite length)
-        // but it does the trick of doing a backspace correctly, even for unicode strings.
-        {
-            let mut input_field = self.input.lock().expect("poison");
-            let cursor_pos: usize = *self.cursor_pos.lock().expect("poison");
-            if cursor_pos == 0 {
-                return;
-            }
-            let pre = Self::pre_cursor(&input_field, cursor_pos - 1)
-                .unwrap_or("".to_string())
-                .replace("<", "&lt;");
-            let post = Self::post_cursor(&input_field, cursor_pos - 1)
-                .unwrap_or("".to_string())
-                .replace("<", "&lt;");
-
-            let new_input = format!("{}{}", pre, post);
-            *input_field = new_input;
-        }
-        self.move_cursor_left();
-        self.update_label(None);
+    // the text entered so far
+    pub fn text(&self) -> String {
+        self.imp().text()
     }
 
-    pub fn reset_input(&self) {
-        {
-            let mut input_field = self.input.lock().expect("poison");
-            let new_input = "".to_string();
-            *input_field = new_input;
-        }
-        self.update_label(None);
+    pub fn set_text(&self, text: &str) {
+        self.set_property("text", text);
     }
 
-    fn show_active_key_layer(&self) {
-        let mut idx: usize = 0;
-        for layer in &self.keys_layers {
-            if idx == *self.active_key_layer.lock().expect("poison") {
-                layer.show_all();
-            } else {
-                layer.hide();
-            }
-            idx += 1;
-        }
+    pub fn cursor_position(&self) -> usize {
+        self.property::<u32>("cursor-position") as usize
     }
 
-    pub fn show(&self, close_action: DialogCloseAction) {
-        *self.close_action.lock().expect("poison") = close_action;
-        *self.active_key_layer.lock().expect("poison") = 0;
-        self.widget.show();
-        self.prompt.show();
-        self.screen.show();
-        self.show_active_key_layer();
+    pub fn set_cursor_position(&self, cursor: usize) {
+        self.set_property("cursor-position", cursor as u32);
     }
 
-    pub fn hide(&self) {
-        self.widget.hide();
+    pub fn prompt_text(&self) -> String {
+        self.property("prompt")
     }
-    fn next_keyset(&self) {
-        let active_layer: usize = *self.active_key_layer.lock().expect("poison");
-        let new_layer = (active_layer + 1) % (self.keys_layers.len());
-        *self.active_key_layer.lock().expect("poison") = new_layer;
-        self.show_active_key_layer();
+
+    pub fn set_prompt_text(&self, prompt_text: &str) {
+        self.set_property("prompt", prompt_text);
     }
 
-    fn button_label_text(button: &gtk::Button) -> String {
-        let child = button.child();
-        if let Some(widget) = child {
-            // {
-            let labelwidget = widget.downcast_ref::<Label>();
-            if let Some(label) = labelwidget {
-                return label.text().to_string();
-            }
-        }
-        return "".to_string();
+    pub fn accept(&self) -> String {
+        self.property("accept")
+    }
+
+    pub fn set_accept(&self, accept: &str) {
+        self.set_property("accept", accept);
+    }
+
+    pub fn insert_mode(&self) -> bool {
+        self.property("insert-mode")
+    }
+
+    pub fn set_insert_mode(&self, insert_mode: bool) {
+        self.set_property("insert-mode", insert_mode);
+    }
+
+    pub fn purpose(&self) -> InputPurpose {
+        self.imp().purpose.get()
+    }
+
+    pub fn connect_changed<F: Fn(&Self) + 'static>(&self, f: F) -> glib::SignalHandlerId {
+        self.connect_local("changed", false, move |values| {
+            f(&values[0].get::<Self>().unwrap());
+            None
+        })
+    }
+
+    pub fn connect_activate<F: Fn(&Self) + 'static>(&self, f: F) -> glib::SignalHandlerId {
+        self.connect_local("activate", false, move |values| {
+            f(&values[0].get::<Self>().unwrap());
+            None
+        })
     }
 
-    pub fn handle_key(
+    pub fn connect_cancelled<F: Fn(&Self) + 'static>(&self, f: F) -> glib::SignalHandlerId {
+        self.connect_local("cancelled", false, move |values| {
+            f(&values[0].get::<Self>().unwrap());
+            None
+        })
+    }
+
+    pub fn reset_input(&self) {
+        self.imp().reset_input();
+    }
+
+    // Shows the keyboard; `on_close` is called once it closes again.
+    pub fn open(&self, on_close: impl FnOnce(DialogResult) + 'static) {
+        self.imp().open(Box::new(on_close));
+    }
+
+    // Asks for a line of text: shows the keyboard with the given prompt and
+    // resolves to the text entered, or None if cancelled.
+    pub fn prompt(
         &self,
-        shared: &std::sync::MutexGuard<SharedData>,
-        button_label: &str,
-        special_button_name: &str,
-    ) {
-        if special_button_name == ID_DISABLED {
-            return;
-        }
-        if special_button_name == ID_BACKSPACE {
-            self.backspace();
-            return;
+        prompt_text: &str,
+        options: PromptOptions,
+    ) -> impl std::future::Future<Output = Option<String>> {
+        self.set_prompt_text(prompt_text);
+        self.reset_input();
+        let cursor = text_model::grapheme_count(&options.text);
+        self.imp()
+            .edit(|model| model.set_text(&options.text, cursor));
+        let result: Modal<DialogResult> = modal(|done| self.open(done));
+        async move {
+            match result.await {
+                Some(DialogResult::Ok(text)) => Some(text),
+                _ => None,
+            }
         }
+    }
 
-        if special_button_name == "" {
-            self.append_input(button_label);
-            return;
-        }
-        if special_button_name == ID_SHIFT {
-            self.next_keyset();
-            return;
-        }
-        if special_button_name == ID_LEFT {
-            self.move_cursor_left();
-            return;
-        }
-        if special_button_name == ID_RIGHT {
-            self.move_cursor_right();
-            return;
-        }
-        if special_button_name == ID_INSERT {
-            let mut insmode = self.insert_mode.lock().expect("poison");
-            *insmode = !*insmode;
-            return;
-        }
-        if special_button_name == ID_DELETE {
-            self.del_input();
-            return;
-        }
-        if special_button_name == ID_ENTER {
-            self.hide();
-            let action = self.close_action.lock().expect("poison");
-            action(shared, DialogResult::Ok);
-            return;
-        }
-        if special_button_name == ID_CANCEL {
-            self.hide();
-            let action = self.close_action.lock().expect("poison");
-            action(shared, DialogResult::Cancel);
-            return;
-        }
+    pub fn layout_name(&self) -> String {
+        self.imp().layout_name()
     }
 
-    fn button_callback(button: &gtk::Button, shared_data: &Arc<Mutex<SharedData>>) {
-        // handles keyboard button mouse clicks, mostly.
-        // Our button contains a label which contains the text (so that button width
-        // is kept fixed) so we need some trickery to read the button label.
-        let button_label = Self::button_label_text(&button);
-        //let button_name = button.name().unwrap();
-        let shared = shared_data.lock().expect("poison");
-        let virtual_keyboard = shared.virtual_keyboard.as_ref().unwrap();
+    pub fn set_layout(&self, name: &str) -> Result<(), LayoutError> {
+        self.imp().set_layout(name)
+    }
 
-        let name_property: glib::Value = button.property::<glib::Value>("name");
-        let special_button_name = if let Ok(string_value) = name_property.get::<String>() {
-            if string_value == "" {
-                "".to_string()
-            } else {
-                // special button clicked!
-                string_value
-            }
+    // Follows the allocated size: portrait while taller than wide.
+    pub fn orientation(&self) -> LayoutOrientation {
+        self.imp().orientation.get()
+    }
+
+    pub fn handle_key(&self, button_label: &str, special_button_name: &str) {
+        self.imp().handle_key(button_label, special_button_name);
+    }
+
+    // Switch access: while scanning, a highlight steps through the keys and
+    // the switch (the options' switch key, or `scan_switch()`) picks the
+    // highlighted one; see scanning.rs.
+    pub fn set_scanning(&self, scanning: bool) {
+        let imp = self.imp();
+        imp.scanning.set(scanning);
+        if scanning {
+            imp.start_scanning();
         } else {
-            "".to_string()
-        };
-        virtual_keyboard.handle_key(&shared, &button_label, &special_button_name);
-    }
-
-    fn define_keysets() -> Vec<Vec<KeyDef>> {
-        let mut keys: Vec<Vec<KeyDef>> = vec![];
-
-        let mut row: Vec<KeyDef> = vec![
-            (
-                0.5,
-                "spacer".to_string(),
-                ["".to_string(), "".to_string(), "".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["q".to_string(), "Q".to_string(), "1".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["w".to_string(), "W".to_string(), "2".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["e".to_string(), "E".to_string(), "3".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["r".to_string(), "R".to_string(), "4".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["t".to_string(), "T".to_string(), "5".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["y".to_string(), "Y".to_string(), "6".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["u".to_string(), "U".to_string(), "7".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["i".to_string(), "I".to_string(), "8".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["o".to_string(), "O".to_string(), "9".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["p".to_string(), "P".to_string(), "0".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["-".to_string(), "_".to_string(), "¬".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["+".to_string(), "=".to_string(), "€".to_string()],
-            ),
-            (
-                2.0,
-                ID_BACKSPACE.to_string(),
-                ["⌫".to_string(), "⌫".to_string(), "⌫".to_string()],
-            ),
-        ]
-        .to_vec();
-        keys.push(row.clone());
-        row = [
-            (
-                1.0,
-                ID_DELETE.to_string(),
-                [
-                    SYMBOL_DELETE.to_string(),
-                    SYMBOL_DELETE.to_string(),
-                    SYMBOL_DELETE.to_string(),
-                ],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["a".to_string(), "A".to_string(), "!".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["s".to_string(), "S".to_string(), "\"".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["d".to_string(), "D".to_string(), "£".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["f".to_string(), "F".to_string(), "$".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["g".to_string(), "G".to_string(), "%".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["h".to_string(), "H".to_string(), "^".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["j".to_string(), "J".to_string(), "&".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["k".to_string(), "K".to_string(), "*".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["l".to_string(), "L".to_string(), "(".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                [";".to_string(), ":".to_string(), ")".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["'".to_string(), "@".to_string(), "`".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["#".to_string(), "~".to_string(), "#".to_string()],
-            ),
-            (
-                1.0,
-                ID_INSERT.to_string(),
-                [
-                    SYMBOL_INSERT.to_string(),
-                    SYMBOL_INSERT.to_string(),
-                    SYMBOL_INSERT.to_string(),
-                ],
-            ),
-        ]
-        .to_vec();
-        keys.push(row.clone());
-        row = [
-            (
-                1.75,
-                ID_SHIFT.to_string(),
-                [
-                    SYMBOL_SHIFT.to_string(),
-                    SYMBOL_SHIFT.to_string(),
-                    SYMBOL_SHIFT.to_string(),
-                ],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["z".to_string(), "Z".to_string(), "{".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["x".to_string(), "X".to_string(), "}".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["c".to_string(), "C".to_string(), "[".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["v".to_string(), "V".to_string(), "]".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["b".to_string(), "B".to_string(), "<".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["n".to_string(), "N".to_string(), ">".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["m".to_string(), "M".to_string(), "|".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                [",".to_string(), "<".to_string(), ",".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                [".".to_string(), ">".to_string(), ".".to_string()],
-            ),
-            (
-                1.0,
-                "".to_string(),
-                ["/".to_string(), "?".to_string(), "\\".to_string()],
-            ),
-            (
-                3.0,
-                "spacer".to_string(),
-                ["".to_string(), "".to_string(), "".to_string()],
-            ),
-        ]
-        .to_vec();
-        keys.push(row.clone());
-        row = [
-            (
-                3.0,
-                ID_CANCEL.to_string(),
-                [
-                    SYMBOL_CANCEL.to_string(),
-                    SYMBOL_CANCEL.to_string(),
-                    SYMBOL_CANCEL.to_string(),
-                ],
-            ),
-            (
-                0.25,
-                "spacer".to_string(),
-                ["".to_string(), "".to_string(), "".to_string()],
-            ),
-            (
-                1.0,
-                ID_LEFT.to_string(),
-                [
-                    SYMBOL_LEFT.to_string(),
-                    SYMBOL_LEFT.to_string(),
-                    SYMBOL_LEFT.to_string(),
-                ],
-            ),
-            (
-                8.0,
-                "".to_string(),
-                [" ".to_string(), " ".to_string(), " ".to_string()],
-            ),
-            (
-                1.0,
-                ID_RIGHT.to_string(),
-                [
-                    SYMBOL_RIGHT.to_string(),
-                    SYMBOL_RIGHT.to_string(),
-                    SYMBOL_RIGHT.to_string(),
-                ],
-            ),
-            (
-                0.25,
-                "spacer".to_string(),
-                ["".to_string(), "".to_string(), "".to_string()],
-            ),
-            (
-                3.0,
-                ID_ENTER.to_string(),
-                [
-                    SYMBOL_ENTER.to_string(),
-                    SYMBOL_ENTER.to_string(),
-                    SYMBOL_ENTER.to_string(),
-                ],
-            ),
-        ]
-        .to_vec();
-        keys.push(row.clone());
-        keys
-    }
-
-    fn _create_widget(
-        shared_data: Arc<Mutex<SharedData>>,
-        prompt: &Label,
-        accept: &str,
-        screen: &Label,
-        keys_layers: &mut Vec<gtk::Box>,
-    ) -> gtk::Box {
-        // define the button event handler
-        let shared_callback = move |button: &gtk::Button| {
-            Self::button_callback(button, &shared_data);
-        };
-        screen.set_xalign(0.0);
-        // draw the keyboard
-        let keys = Self::define_keysets();
-
-        let mut rowframes: Vec<gtk::Box> = vec![];
-
-        for keyset in 0..3 {
-            let keys_layer = gtk::Box::new(gtk::Orientation::Vertical, 3);
-            for row in &keys {
-                let rowframe = gtk::Box::builder().name("keyrow").build();
-                rowframe.set_width_request(SCREEN_WIDTH - (BORDER_WIDTH * 2));
-                let style_context = rowframe.style_context();
-                style_context.add_class("keyboard_button_row");
-                for key in row {
-                    let (width, name, labels) = key;
-                    let label = labels[keyset].clone();
-
-                    let w: i32 = (width * 32.0) as i32;
-                    if name == "spacer" {
-                        let spacer_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
-                        spacer_box.set_width_request(w);
-                        rowframe.pack_start(&spacer_box, false, false, 0);
-                    } else {
-                        let button = Button::builder()
-                            .name(name.clone())
-                            .width_request(w)
-                            .build();
-                        let mut disabled = false;
-                        if accept != "" {
-                            if !accept.contains(&label) {
-                                if name == "" {
-                                    disabled = true;
-                                }
-                            }
-                        }
-                        let button_label = Label::new(Some(&label));
-                        button_label.set_width_request(w);
-                        button.add(&button_label);
-
-                        button.connect_clicked(shared_callback.clone());
-                        let style_context = button.style_context();
-                        if disabled {
-                            style_context.add_class("keyboard_button_disabled");
-                            button.set_property("name", ID_DISABLED.to_string());
-                        } else {
-                            style_context.add_class("keyboard_button");
-                            button.set_property("name", name);
-                        }
-
-                        button.set_hexpand(true);
-                        rowframe.pack_start(&button, false, true, 0);
-                    }
-                }
-                rowframes.push(rowframe);
-            }
-            for bar in &rowframes {
-                keys_layer.pack_start(bar, true, true, 0);
-            }
-            keys_layer.set_height_request(SCREEN_HEIGHT * 3 / 4);
-            keys_layer.hide();
-            keys_layers.push(keys_layer);
+            imp.stop_scanning();
         }
-        let virtual_keyboard = gtk::Box::new(gtk::Orientation::Vertical, 5);
-        prompt.set_height_request(SCREEN_HEIGHT * 5 / 40);
-        screen.set_height_request(SCREEN_HEIGHT * 5 / 40);
-
-        virtual_keyboard.pack_start(prompt, true, true, 0);
-        virtual_keyboard.pack_start(screen, true, true, 0);
-        for keys_layer in keys_layers {
-            virtual_keyboard.pack_start(keys_layer, true, true, 0);
-        }
-        virtual_keyboard.set_border_width(BORDER_WIDTH as u32);
-        virtual_keyboard
     }
-    pub fn new(
-        shared_data: Arc<Mutex<SharedData>>,
-        prompt_text: &str,
-        accept: &str,
-    ) -> VirtualKeyboard {
-        // Create a CSS provider
-        let css_provider = CssProvider::new();
-        // Load the CSS data
-        css_provider
-            .load_from_data(VIRTUAL_KEYBOARD_CSS.as_bytes())
-            .expect("Failed to load CSS");
-
-        // Add the CSS provider to the default style context of the button
-        gtk::StyleContext::add_provider_for_screen(
-            &gdk::Screen::default().expect("Error initializing gtk css provider."),
-            &css_provider,
-            gtk::STYLE_PROVIDER_PRIORITY_USER,
-        );
 
-        let prompt = gtk::Label::builder().name("prompt").build();
-        let screen = gtk::Label::builder().name("screen").build();
-        // Note: If choosing a different font for the screen, be sure
-        // it doesn't do ligatures, so that it won't merge letterings for e.g. ff, fi
-        // into a single glyph.
+    pub fn is_scanning(&self) -> bool {
+        self.imp().scanning.get()
+    }
 
-        let mut keys_layers: Vec<gtk::Box> = vec![];
+    pub fn scan_options(&self) -> ScanOptions {
+        self.imp().scanner.borrow().options().clone()
+    }
 
-        prompt.set_text(prompt_text);
-        // only a very limited set of tags is supported by this
-        //screen.set_markup("please type <b>SOMETHING</b>");
+    // Applies from the top of a new scan.
+    pub fn set_scan_options(&self, options: ScanOptions) {
+        self.imp().scanner.borrow_mut().set_options(options);
+        self.imp().start_scanning();
+    }
 
-        let widget = VirtualKeyboard::_create_widget(
-            Arc::clone(&shared_data),
-            &prompt,
-            &accept,
-            &screen,
-            &mut keys_layers,
-        );
-        let instance = VirtualKeyboard {
-            widget,
-            input: Mutex::new("".to_string()),
-            close_action: Mutex::new(|_, _| {}),
-            screen,
-            prompt,
-            active_key_layer: 0.into(),
-            keys_layers,
-            accept: accept.to_string(),
-            cursor_state: Mutex::new(false),
-            insert_mode: Mutex::new(false),
-            cursor_pos: Mutex::new(0),
-        };
-        let shared_data_for_cursor = Arc::clone(&shared_data);
-        // cursor blink timer thread
-        let _source_id =
-            glib::timeout_add_local(std::time::Duration::from_millis(400), move || {
-                VirtualKeyboard::blink_cursor(&shared_data_for_cursor);
-                Continue(true)
-            });
+    pub fn scan_switch(&self) {
+        self.imp().scan_switch();
+    }
 
-        instance
+    // what scanning highlights, for tests and custom highlighting
+    pub fn scan_highlight(&self) -> Highlight {
+        self.imp().scanner.borrow().highlight()
     }
+
+    // Grid focus: while on, the arrow keys (and a rotary encoder's steps)
+    // move a focus highlight across the keys and Enter presses the focused
+    // one; see key_focus.rs. Off, they move the text cursor.
+    pub fn set_key_focus_mode(&self, enabled: bool) {
+        self.imp().set_key_focus_mode(enabled);
+    }
+
+    pub fn is_key_focus_mode(&self) -> bool {
+        self.imp().key_focus_mode.get()
+    }
+
+    pub fn focus_options(&self) -> FocusOptions {
+        self.imp().focus_options.borrow().clone()
+    }
+
+    pub fn set_focus_options(&self, options: FocusOptions) {
+        *self.imp().focus_options.borrow_mut() = options;
+    }
+
+    pub fn move_key_focus(&self, direction: FocusDirection) {
+        self.imp().move_key_focus(direction);
+    }
+
+    pub fn press_focused_key(&self) {
+        self.imp().press_focused_key();
+    }
+
+    // the focused key as row and position in the row, leaving out spacers
+    // and disabled keys
+    pub fn focused_key(&self) -> Option<(usize, usize)> {
+        self.imp().key_focus.borrow().focus()
+    }
+}
+
+impl Screen for VirtualKeyboard {
+    fn widget(&self) -> gtk::Widget {
+        self.clone().upcast()
+    }
+}
+
+#[test]
+fn test_markup_escaping() {
+    // every character that means something in Pango markup, typed and then
+    // edited around, must come back unchanged and be shown escaped
+    for (special, escaped) in [
+        ("<", "&lt;"),
+        (">", "&gt;"),
+        ("&", "&amp;"),
+        ("'", "&#39;"),
+        ("\"", "&quot;"),
+    ] {
+        let mut model = TextModel::new();
+        model.toggle_insert_mode();
+        for text in ["a", special, "b"] {
+            model.type_text(text);
+        }
+        model.move_cursor(Movement::Left, false);
+        model.move_cursor(Movement::Left, false);
+        model.type_text("c");
+        model.move_cursor(Movement::End, false);
+        model.backspace();
+        model.type_text("d");
+        assert_eq!(model.text(), format!("ac{}d", special));
+        assert_eq!(
+            VirtualKeyboard::markup(&model, false, false),
+            format!("ac{}d ", escaped)
+        );
+        assert_eq!(
+            VirtualKeyboard::markup(&model, false, true),
+            format!("ac{}d<u> </u>", escaped)
+        );
+        model.move_cursor(Movement::Home, false);
+        model.move_cursor(Movement::Right, true);
+        model.move_cursor(Movement::Right, true);
+        model.move_cursor(Movement::Right, true);
+        assert_eq!(
+            VirtualKeyboard::markup(&model, false, true),
+            format!(
+                "<span foreground=\"white\" background=\"#3465a4\">ac{}</span>d",
+                escaped
+            )
+        );
+    }
+    // the cursor block can sit on a special character too
+    let mut model = TextModel::new();
+    model.set_text("<&>", 1);
+    assert_eq!(
+        VirtualKeyboard::markup(&model, false, true),
+        "&lt;<u>&amp;</u>&gt;"
+    );
+    // masked input shows no text at all
+    assert_eq!(VirtualKeyboard::markup(&model, true, false), "●●●");
+    assert_eq!(VirtualKeyboard::markup(&model, true, true), "●<u>●</u>●");
 }
 
 #[test]
-fn test_charlen() {
-    assert_eq!(VirtualKeyboard::charlen("abc"), 3);
-    assert_eq!(VirtualKeyboard::charlen("ab€"), 3);
+fn test_default_switch_key() {
+    use crate::modules::scanning::DEFAULT_SWITCH_KEY;
+    assert_eq!(DEFAULT_SWITCH_KEY, *gdk::keys::constants::F12);
 }
 
 #[test]
-fn test_pre_cursor() {
+fn test_focus_keys() {
+    use crate::modules::key_focus::{DEFAULT_NEXT_KEY, DEFAULT_PREVIOUS_KEY, DEFAULT_TOGGLE_KEY};
+    use gdk::keys::constants as keys;
+    assert_eq!(DEFAULT_TOGGLE_KEY, *keys::Tab);
+    assert_eq!(DEFAULT_NEXT_KEY, *keys::Page_Down);
+    assert_eq!(DEFAULT_PREVIOUS_KEY, *keys::Page_Up);
+    let options = FocusOptions::default();
+    // the toggle works in either mode, everything else only in grid focus
     assert_eq!(
-        VirtualKeyboard::pre_cursor("abcde", 0).unwrap_or("".to_string()),
-        ""
+        focus_key(*keys::Tab, &options, false),
+        Some(FocusKey::Toggle)
     );
     assert_eq!(
-        VirtualKeyboard::pre_cursor("abcde", 1).unwrap_or("".to_string()),
-        "a"
+        focus_key(*keys::Tab, &options, true),
+        Some(FocusKey::Toggle)
     );
+    assert_eq!(focus_key(*keys::Left, &options, false), None);
+    assert_eq!(focus_key(*keys::Return, &options, false), None);
     assert_eq!(
-        VirtualKeyboard::pre_cursor("€bcde", 1).unwrap_or("".to_string()),
-        "€"
+        focus_key(*keys::Left, &options, true),
+        Some(FocusKey::Move(FocusDirection::Left))
     );
     assert_eq!(
-        VirtualKeyboard::pre_cursor("€bcde", 2).unwrap_or("".to_string()),
-        "€b"
+        focus_key(*keys::KP_Down, &options, true),
+        Some(FocusKey::Move(FocusDirection::Down))
     );
     assert_eq!(
-        VirtualKeyboard::pre_cursor("€bcde", 5).unwrap_or("".to_string()),
-        "€bcde"
+        focus_key(*keys::Page_Down, &options, true),
+        Some(FocusKey::Move(FocusDirection::Next))
     );
     assert_eq!(
-        VirtualKeyboard::pre_cursor("€bcde", 6).unwrap_or("".to_string()),
-        "€bcde"
+        focus_key(*keys::Return, &options, true),
+        Some(FocusKey::Press)
+    );
+    // typing still types
+    assert_eq!(focus_key(*keys::a, &options, true), None);
+    let options = FocusOptions {
+        toggle_key: *keys::F2,
+        ..FocusOptions::default()
+    };
+    assert_eq!(focus_key(*keys::Tab, &options, true), None);
+    assert_eq!(focus_key(*keys::F2, &options, true), Some(FocusKey::Toggle));
+    // with a modifier held, the arrows select and move by word as usual
+    let options = FocusOptions::default();
+    assert_eq!(
+        translate_focus_key(*keys::Left, gdk::ModifierType::empty(), &options, true),
+        Some(FocusKey::Move(FocusDirection::Left))
+    );
+    assert_eq!(
+        translate_focus_key(*keys::Left, gdk::ModifierType::SHIFT_MASK, &options, true),
+        None
+    );
+    assert_eq!(
+        translate_focus_key(*keys::Return, gdk::ModifierType::CONTROL_MASK, &options, true),
+        None
     );
 }
 
 #[test]
-fn test_on_cursor() {
-    assert_eq!(VirtualKeyboard::on_cursor("a€c€e", 0).unwrap(), "a");
-    assert_eq!(VirtualKeyboard::on_cursor("a€c€e", 1).unwrap(), "€");
-    assert_eq!(VirtualKeyboard::on_cursor("a€c€e", 2).unwrap(), "c");
-    assert_eq!(VirtualKeyboard::on_cursor("a€c€e", 3).unwrap(), "€");
-    assert_eq!(VirtualKeyboard::on_cursor("a€c€e", 4).unwrap(), "e");
-    assert!(VirtualKeyboard::on_cursor("a€c€e", 5).is_none());
-}
-
-#[test]
-fn test_post_cursor() {
-    assert_eq!(VirtualKeyboard::post_cursor("a€c€e", 0).unwrap(), "€c€e");
-    assert_eq!(VirtualKeyboard::post_cursor("a€c€e", 1).unwrap(), "c€e");
-    assert_eq!(VirtualKeyboard::post_cursor("a€c€e", 2).unwrap(), "€e");
-    assert_eq!(VirtualKeyboard::post_cursor("a€c€e", 3).unwrap(), "e");
-    assert!(VirtualKeyboard::post_cursor("a€c€e", 4).is_none());
-    assert!(VirtualKeyboard::post_cursor("a€c€e", 5).is_none());
+fn test_translate_key() {
+    use gdk::keys::constants as keys;
+    let none = gdk::ModifierType::empty();
+    let shift = gdk::ModifierType::SHIFT_MASK;
+    let control = gdk::ModifierType::CONTROL_MASK;
+    let translate = |key: gdk::keys::Key, state: gdk::ModifierType, accept: &str| {
+        translate_key(key, state, InputPurpose::FreeForm, accept)
+    };
+    let plain = |key: &str| Some((key.to_string(), "".to_string()));
+    let special = |id: &str| Some(("".to_string(), id.to_string()));
+
+    assert_eq!(translate(keys::a, none, ""), plain("a"));
+    assert_eq!(translate(keys::A, shift, ""), plain("A"));
+    // beyond Latin-1
+    assert_eq!(translate(keys::EuroSign, none, ""), plain("€"));
+    assert_eq!(translate(keys::Greek_alpha, none, ""), plain("α"));
+    assert_eq!(translate(keys::Cyrillic_zhe, none, ""), plain("ж"));
+    assert_eq!(
+        translate(gdk::keys::Key::from_unicode('漢'), none, ""),
+        plain("漢")
+    );
+    assert_eq!(
+        translate(keys::EuroSign, gdk::ModifierType::MOD5_MASK, ""),
+        plain("€")
+    );
+    // shortcuts are not text
+    assert_eq!(translate(keys::s, control, ""), None);
+    assert_eq!(translate(keys::x, gdk::ModifierType::MOD1_MASK, ""), None);
+    assert_eq!(translate(keys::Tab, none, ""), None);
+    assert_eq!(translate(keys::F1, none, ""), None);
+    // named keys, dead keys and compose
+    assert_eq!(translate(keys::BackSpace, none, ""), special(ID_BACKSPACE));
+    assert_eq!(translate(keys::KP_Enter, none, ""), special(ID_ENTER));
+    assert_eq!(translate(keys::Multi_key, none, ""), special(ID_COMPOSE));
+    assert_eq!(translate(keys::Home, none, ""), special(ID_HOME));
+    assert_eq!(translate(keys::Left, shift, ""), special(ID_SELECT_LEFT));
+    assert_eq!(translate(keys::End, shift, ""), special(ID_SELECT_END));
+    assert_eq!(translate(keys::KP_End, none, ""), special(ID_END));
+    assert_eq!(translate(keys::Left, control, ""), special(ID_WORD_LEFT));
+    assert_eq!(translate(keys::Right, control, ""), special(ID_WORD_RIGHT));
+    assert_eq!(
+        translate(keys::BackSpace, control, ""),
+        special(ID_DELETE_WORD_BACK)
+    );
+    assert_eq!(
+        translate(keys::Delete, control, ""),
+        special(ID_DELETE_WORD_FORWARD)
+    );
+    assert_eq!(translate(keys::a, control, ""), special(ID_SELECT_ALL));
+    assert_eq!(translate(keys::c, control, ""), special(ID_COPY));
+    assert_eq!(translate(keys::X, control | shift, ""), special(ID_CUT));
+    assert_eq!(translate(keys::v, control, ""), special(ID_PASTE));
+    assert_eq!(translate(keys::z, control, ""), special(ID_UNDO));
+    assert_eq!(translate(keys::y, control, ""), special(ID_REDO));
+    assert_eq!(translate(keys::Z, control | shift, ""), special(ID_REDO));
+    assert_eq!(
+        translate(keys::dead_acute, none, ""),
+        Some(("´".to_string(), ID_DEAD.to_string()))
+    );
+    // accept filter, and the upper case hex keypad
+    assert_eq!(translate(keys::a, none, "0123"), None);
+    assert_eq!(translate(keys::_1, none, "0123"), plain("1"));
+    assert_eq!(
+        translate_key(keys::f, none, InputPurpose::Hex, InputPurpose::Hex.accept()),
+        plain("F")
+    );
 }
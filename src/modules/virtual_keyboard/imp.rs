@@ -0,0 +1,1457 @@
+// The GObject side of the virtual keyboard: its state, properties and
+// signals, and the key handling behind them. The public API is on the
+// wrapper type in virtual_keyboard.rs.
+use super::*;
+use crate::modules::lifecycle::Owned;
+use glib::subclass::signal::SignalId;
+use glib::subclass::Signal;
+use glib::translate::IntoGlib;
+use gtk::atk;
+use std::cell::{Cell, RefCell};
+use std::sync::OnceLock;
+
+// A key that is being held down, and once the delay has passed, the popover
+// with its alternates.
+struct LongPress {
+    button: gtk::Button,
+    alternates: Vec<String>,
+    timer: Option<glib::SourceId>,
+    popover: Option<gtk::Popover>,
+    alternate_buttons: Vec<gtk::Button>,
+}
+
+#[derive(Default)]
+pub struct VirtualKeyboard {
+    model: RefCell<TextModel>,
+    close_action: RefCell<Option<DialogCallback>>,
+    prompt: Label,
+    screen: Label,
+    active_key_layer: Cell<usize>,
+    modifiers: RefCell<ModifierState>,
+    long_press: RefCell<Option<LongPress>>,
+    suppress_click: Cell<bool>,
+    composer: RefCell<Composer>,
+    keys_layers: RefCell<Vec<gtk::Box>>,
+    layouts: RefCell<Vec<KeyboardLayout>>,
+    active_layout: Cell<usize>,
+    pub(super) purpose: Cell<InputPurpose>,
+    cursor_state: Cell<bool>,
+    // the arrangement of the keys, following the allocated size
+    pub(super) orientation: Cell<LayoutOrientation>,
+    // how much the CSS fonts are scaled; 0 until first allocated
+    font_scale: Cell<f64>,
+    relayout_pending: Cell<bool>,
+    // the cursor blink, which only runs while the keyboard is on screen
+    while_shown: Owned,
+    // switch access; the timer runs while scanning and on screen
+    pub(super) scanning: Cell<bool>,
+    pub(super) scanner: RefCell<Scanner>,
+    scan_timer: Owned,
+    // D-pad and rotary encoder navigation: while on, the arrow keys move
+    // the key focus rather than the text cursor
+    pub(super) key_focus_mode: Cell<bool>,
+    pub(super) key_focus: RefCell<KeyFocus>,
+    pub(super) focus_options: RefCell<FocusOptions>,
+    history: RefCell<UndoHistory>,
+    pub(super) accept: RefCell<String>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for VirtualKeyboard {
+    const NAME: &'static str = "RustgtkVirtualKeyboard";
+    type Type = super::VirtualKeyboard;
+    type ParentType = gtk::Box;
+}
+
+impl ObjectImpl for VirtualKeyboard {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: OnceLock<Vec<glib::ParamSpec>> = OnceLock::new();
+        PROPERTIES.get_or_init(|| {
+            vec![
+                glib::ParamSpecString::builder("text").build(),
+                // in grapheme clusters, like everything in the TextModel
+                glib::ParamSpecUInt::builder("cursor-position").build(),
+                glib::ParamSpecString::builder("prompt").build(),
+                // the characters that can be typed; "" accepts anything
+                glib::ParamSpecString::builder("accept").build(),
+                glib::ParamSpecBoolean::builder("insert-mode").build(),
+            ]
+        })
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "text" => {
+                let text: String = value.get().unwrap_or_default();
+                let cursor = text_model::grapheme_count(&text);
+                let before = self.snapshot();
+                self.edit(|model| model.set_text(&text, cursor));
+                self.record_edit(before, EditKind::Other);
+            }
+            "cursor-position" => {
+                let cursor = value.get::<u32>().unwrap_or_default() as usize;
+                let before = self.snapshot();
+                self.edit(|model| {
+                    let text = model.text().to_string();
+                    model.set_text(&text, cursor);
+                });
+                self.record_edit(before, EditKind::Other);
+            }
+            "prompt" => {
+                let prompt: Option<String> = value.get().unwrap_or_default();
+                self.prompt.set_text(&prompt.unwrap_or_default());
+                self.update_accessible_screen();
+            }
+            "accept" => {
+                let accept: Option<String> = value.get().unwrap_or_default();
+                *self.accept.borrow_mut() = accept.unwrap_or_default();
+                // keys outside the new set are disabled
+                self.activate_layout(self.active_layout.get());
+            }
+            "insert-mode" => {
+                let insert_mode: bool = value.get().unwrap_or_default();
+                if insert_mode != self.model.borrow().insert_mode() {
+                    self.edit(TextModel::toggle_insert_mode);
+                }
+            }
+            _ => unreachable!("unknown property {}", pspec.name()),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "text" => self.text().to_value(),
+            "cursor-position" => (self.model.borrow().cursor() as u32).to_value(),
+            "prompt" => self.prompt.text().to_value(),
+            "accept" => self.accept.borrow().to_value(),
+            "insert-mode" => self.model.borrow().insert_mode().to_value(),
+            _ => unreachable!("unknown property {}", pspec.name()),
+        }
+    }
+
+    fn signals() -> &'static [Signal] {
+        static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+        SIGNALS.get_or_init(|| {
+            vec![
+                // the text was edited
+                Signal::builder("changed").build(),
+                // OK was pressed; the text is in the "text" property
+                Signal::builder("activate").build(),
+                Signal::builder("cancelled").build(),
+            ]
+        })
+    }
+
+    fn constructed(&self) {
+        self.parent_constructed();
+        // the styling comes from the current theme, installed once for all
+        // keyboards
+        theme::themes();
+
+        let obj = self.obj();
+        obj.set_orientation(gtk::Orientation::Vertical);
+        obj.set_spacing(5);
+        obj.set_border_width(BORDER_WIDTH as u32);
+
+        self.prompt.set_widget_name("prompt");
+        self.screen.set_widget_name("screen");
+        // Note: If choosing a different font for the screen, be sure
+        // it doesn't do ligatures, so that it won't merge letterings for e.g. ff, fi
+        // into a single glyph.
+        self.screen.set_xalign(0.0);
+        // only a very limited set of tags is supported by this
+        //screen.set_markup("please type <b>SOMETHING</b>");
+        obj.pack_start(&self.prompt, true, true, 0);
+        obj.pack_start(&self.screen, true, true, 0);
+        // to assistive technology the input is an editable text entry
+        if let Some(accessible) = self.screen.accessible() {
+            accessible.set_role(atk::Role::Entry);
+            // AtkState is the StateType's value, not a bit in a set
+            let editable = atk::StateType::Editable.into_glib() as atk::State;
+            accessible.notify_state_change(editable, true);
+        }
+        self.update_accessible_screen();
+        // the keys are built by configure(), or on the first map
+    }
+
+    fn dispose(&self) {
+        self.on_destroy();
+    }
+}
+
+impl WidgetImpl for VirtualKeyboard {
+    fn size_allocate(&self, allocation: &gtk::Allocation) {
+        // The children get shares of the allocation rather than their
+        // requested sizes, so the keyboard fills any screen the same way.
+        let obj = self.obj();
+        obj.set_allocation(allocation);
+        let border = obj.border_width() as i32;
+        let spacing = obj.spacing();
+        let children: Vec<gtk::Widget> = obj
+            .children()
+            .into_iter()
+            .filter(|child| child.is_visible())
+            .collect();
+        let shares: Vec<i32> = children
+            .iter()
+            .map(|child| {
+                if child == self.prompt.upcast_ref::<gtk::Widget>() {
+                    PROMPT_SHARE
+                } else if child == self.screen.upcast_ref::<gtk::Widget>() {
+                    SCREEN_SHARE
+                } else {
+                    KEYS_SHARE
+                }
+            })
+            .collect();
+        let total_shares: i32 = shares.iter().sum();
+        let gaps = spacing * (children.len() as i32 - 1).max(0);
+        let width = (allocation.width() - 2 * border).max(0);
+        let height = (allocation.height() - 2 * border - gaps).max(0);
+        let mut y = allocation.y() + border;
+        for (child, share) in children.iter().zip(shares) {
+            let child_height = height * share / total_shares;
+            // GTK expects to have been asked for the sizes first
+            child.preferred_width();
+            child.preferred_height();
+            child.size_allocate(&gtk::Allocation::new(
+                allocation.x() + border,
+                y,
+                width,
+                child_height,
+            ));
+            y += child_height + spacing;
+        }
+        self.schedule_relayout(allocation.width(), allocation.height());
+    }
+
+    fn map(&self) {
+        if self.layouts.borrow().is_empty() {
+            // made without a layout, e.g. from a .ui file
+            let accept = self.accept.borrow().clone();
+            self.configure(vec![KeyboardLayout::builtin()], self.purpose.get(), &accept);
+        }
+        self.parent_map();
+        self.on_show();
+    }
+
+    fn unmap(&self) {
+        self.on_hide();
+        self.parent_unmap();
+    }
+}
+
+// What runs only while the keyboard is on screen goes into `while_shown`,
+// started from map and released from unmap.
+impl VirtualKeyboard {
+    fn on_show(&self) {
+        let this = self.obj().downgrade();
+        self.while_shown.timeout(CURSOR_BLINK_INTERVAL, move || {
+            if let Some(virtual_keyboard) = this.upgrade() {
+                virtual_keyboard.imp().blink_cursor();
+            }
+        });
+        self.start_scanning();
+    }
+
+    fn on_hide(&self) {
+        self.while_shown.release();
+        self.stop_scanning();
+        // a popover left open would outlive the keyboard on screen
+        self.close_alternates();
+    }
+
+    fn on_destroy(&self) {
+        self.while_shown.release();
+        self.scan_timer.release();
+        // along with the long press timer
+        self.close_alternates();
+    }
+}
+
+impl ContainerImpl for VirtualKeyboard {}
+
+impl BoxImpl for VirtualKeyboard {}
+
+impl VirtualKeyboard {
+    // Replaces the layouts, purpose and accepted characters the keyboard
+    // was constructed with.
+    pub(super) fn configure(
+        &self,
+        layouts: Vec<KeyboardLayout>,
+        purpose: InputPurpose,
+        accept: &str,
+    ) {
+        self.purpose.set(purpose);
+        *self.layouts.borrow_mut() = layouts;
+        *self.accept.borrow_mut() = accept.to_string();
+        self.activate_layout(0);
+    }
+
+    fn update_label(&self, cursor: Option<&str>) {
+        let cursorshape = if let Some(c) = cursor { c } else { "_" };
+        let markup = super::VirtualKeyboard::markup(
+            &self.model.borrow(),
+            self.purpose.get().is_masked(),
+            cursorshape == "_",
+        );
+        self.screen.set_markup(&markup);
+    }
+    fn blink_cursor(&self) {
+        let cs = self.cursor_state.get();
+        self.cursor_state.set(!cs);
+        self.update_label(Some(if cs { "_" } else { " " }));
+    }
+
+    // the text entered so far
+    pub(super) fn text(&self) -> String {
+        self.model.borrow().text().to_string()
+    }
+
+    pub(super) fn edit(&self, change: impl FnOnce(&mut TextModel)) {
+        let before = self.model.borrow().clone();
+        change(&mut self.model.borrow_mut());
+        // also keeps the cursor visible while moving it
+        self.update_label(None);
+        // property bindings and signal handlers may edit again, so they are
+        // only told once the model is no longer borrowed
+        let (text_changed, cursor_moved, mode_changed) = {
+            let after = self.model.borrow();
+            (
+                after.text() != before.text(),
+                after.cursor() != before.cursor(),
+                after.insert_mode() != before.insert_mode(),
+            )
+        };
+        let obj = self.obj();
+        if text_changed {
+            obj.notify("text");
+            obj.emit_by_name::<()>("changed", &[]);
+        }
+        if text_changed || cursor_moved {
+            self.update_accessible_screen();
+        }
+        if cursor_moved {
+            self.caret_moved();
+            obj.notify("cursor-position");
+        }
+        if mode_changed {
+            obj.notify("insert-mode");
+        }
+    }
+
+    // The input's accessible name is the prompt; its description says
+    // where the cursor is.
+    fn update_accessible_screen(&self) {
+        if let Some(accessible) = self.screen.accessible() {
+            accessible.set_name(&self.prompt.text());
+            accessible.set_description(&accessibility::cursor_description(&self.model.borrow()));
+        }
+    }
+
+    fn caret_moved(&self) {
+        // the label's accessible only has this signal if it implements
+        // AtkText
+        if let Some(accessible) = self.screen.accessible() {
+            if SignalId::lookup("text-caret-moved", accessible.type_()).is_some() {
+                let cursor = self.model.borrow().cursor() as i32;
+                accessible.emit_by_name::<()>("text-caret-moved", &[&cursor]);
+            }
+        }
+    }
+
+    // Has screen readers say what a key press did.
+    fn announce(&self, message: &str) {
+        let accessible = match self.obj().accessible() {
+            Some(accessible) => accessible,
+            None => return,
+        };
+        if SignalId::lookup("announcement", accessible.type_()).is_some() {
+            accessible.emit_by_name::<()>("announcement", &[&message]);
+        } else {
+            // ATK before 2.46 can't announce; a changed description is
+            // read out as well
+            accessible.set_description(message);
+        }
+    }
+
+    fn layer_announcement(&self) -> String {
+        let modifiers = self.modifiers.borrow();
+        accessibility::layer_announcement(modifiers.shift(), modifiers.symbols(), modifiers.layer())
+    }
+
+    fn has_selection(&self) -> bool {
+        self.model.borrow().selection().is_some()
+    }
+
+    fn copy_selection(&self) {
+        // hidden input (e.g. a PIN) never goes to the clipboard
+        if self.purpose.get().is_masked() {
+            return;
+        }
+        let selected_text = self.model.borrow().selected_text().map(str::to_string);
+        if let Some(text) = selected_text {
+            self.screen
+                .clipboard(&gdk::SELECTION_CLIPBOARD)
+                .set_text(&text);
+        }
+    }
+
+    fn cut_selection(&self) {
+        if self.purpose.get().is_masked() {
+            return;
+        }
+        self.copy_selection();
+        self.edit(|model| {
+            model.delete_selection();
+        });
+    }
+
+    fn paste(&self) {
+        // The clipboard may be owned by another application, so the text
+        // arrives later. The callback can also run straight away when we own
+        // the clipboard ourselves, while the key handler is still running, so
+        // the actual insert is always deferred to an idle callback.
+        let this = self.obj().downgrade();
+        self.screen
+            .clipboard(&gdk::SELECTION_CLIPBOARD)
+            .request_text(move |_clipboard, text| {
+                let text = match text {
+                    Some(text) => text.to_string(),
+                    None => return,
+                };
+                glib::idle_add_local_once(move || {
+                    if let Some(virtual_keyboard) = this.upgrade() {
+                        virtual_keyboard.imp().insert_pasted_text(&text);
+                    }
+                });
+            });
+    }
+
+    fn insert_pasted_text(&self, text: &str) {
+        // the input is a single line; drop line breaks, tabs and anything
+        // the caller doesn't accept
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_control())
+            .filter(|c| self.accept.borrow().is_empty() || self.accept.borrow().contains(*c))
+            .collect();
+        if text.is_empty() {
+            return;
+        }
+        let before = self.snapshot();
+        self.edit(|model| model.insert(&text));
+        self.record_edit(before, EditKind::Other);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let model = self.model.borrow();
+        Snapshot {
+            text: model.text().to_string(),
+            cursor: model.cursor(),
+        }
+    }
+
+    fn record_edit(&self, before: Snapshot, kind: EditKind) {
+        // adds an undo step if the text changed since before
+        let after = self.snapshot();
+        let mut history = self.history.borrow_mut();
+        if after.text != before.text {
+            history.record(before, kind);
+        } else if after.cursor != before.cursor {
+            history.break_coalescing();
+        }
+    }
+
+    fn restore(&self, snapshot: Snapshot) {
+        self.composer.borrow_mut().reset();
+        self.update_compose_keys();
+        self.edit(|model| model.set_text(&snapshot.text, snapshot.cursor));
+    }
+
+    fn undo(&self) {
+        let current = self.snapshot();
+        let previous = self.history.borrow_mut().undo(current);
+        if let Some(previous) = previous {
+            self.restore(previous);
+        }
+    }
+
+    fn redo(&self) {
+        let current = self.snapshot();
+        let next = self.history.borrow_mut().redo(current);
+        if let Some(next) = next {
+            self.restore(next);
+        }
+    }
+
+    pub(super) fn reset_input(&self) {
+        // a fresh start: nothing to undo either
+        self.history.borrow_mut().clear();
+        self.edit(TextModel::clear);
+    }
+
+    fn show_active_key_layer(&self) {
+        let mut idx: usize = 0;
+        for layer in self.keys_layers.borrow().iter() {
+            if idx == self.active_key_layer.get() {
+                layer.show_all();
+            } else {
+                layer.hide();
+            }
+            idx += 1;
+        }
+        self.update_scan_rows();
+        self.update_key_focus_rows();
+    }
+
+    // The buttons scanning and key focus go through: the keys of the layer
+    // showing, row by row and from left to right, less the disabled ones.
+    fn key_rows(&self) -> Vec<(gtk::Grid, Vec<gtk::Button>)> {
+        let keys_layers = self.keys_layers.borrow();
+        let layer = match keys_layers.get(self.active_key_layer.get()) {
+            Some(layer) => layer,
+            None => return vec![],
+        };
+        layer
+            .children()
+            .into_iter()
+            .filter_map(|row| row.downcast::<gtk::Grid>().ok())
+            .map(|rowframe| {
+                let mut buttons: Vec<gtk::Button> = rowframe
+                    .children()
+                    .into_iter()
+                    .filter_map(|key| key.downcast::<gtk::Button>().ok())
+                    .filter(|button| button.widget_name() != ID_DISABLED)
+                    .collect();
+                // a grid lists its children in no particular order
+                buttons.sort_by_key(|button| rowframe.cell_left_attach(button));
+                (rowframe, buttons)
+            })
+            .collect()
+    }
+
+    fn update_scan_rows(&self) {
+        if !self.scanning.get() {
+            return;
+        }
+        let rows = self
+            .key_rows()
+            .iter()
+            .map(|(_, buttons)| buttons.len())
+            .collect();
+        self.scanner.borrow_mut().set_rows(rows);
+        self.show_scan_highlight();
+    }
+
+    fn show_scan_highlight(&self) {
+        let highlight = self.scanner.borrow().highlight();
+        for (row, (rowframe, buttons)) in self.key_rows().iter().enumerate() {
+            Self::set_style_class(rowframe, "scan_highlight", highlight == Highlight::Row(row));
+            for (key, button) in buttons.iter().enumerate() {
+                Self::set_style_class(
+                    button,
+                    "scan_highlight",
+                    highlight == Highlight::Key(row, key),
+                );
+            }
+        }
+    }
+
+    // Starts scanning from the top, if it is on and the keyboard is on
+    // screen.
+    pub(super) fn start_scanning(&self) {
+        if !self.scanning.get() || !self.obj().is_mapped() {
+            return;
+        }
+        self.update_scan_rows();
+        self.scanner.borrow_mut().start();
+        self.restart_scan_timer();
+    }
+
+    pub(super) fn stop_scanning(&self) {
+        self.scan_timer.release();
+        self.scanner.borrow_mut().stop();
+        self.show_scan_highlight();
+    }
+
+    fn restart_scan_timer(&self) {
+        // a new highlight always gets the full interval
+        self.scan_timer.release();
+        let interval = self.scanner.borrow().options().interval;
+        let this = self.obj().downgrade();
+        self.scan_timer.timeout(interval, move || {
+            if let Some(virtual_keyboard) = this.upgrade() {
+                virtual_keyboard.imp().scan_tick();
+            }
+        });
+        self.show_scan_highlight();
+    }
+
+    fn scan_tick(&self) {
+        self.scanner.borrow_mut().tick();
+        self.show_scan_highlight();
+    }
+
+    fn update_key_focus_rows(&self) {
+        let rows = self
+            .key_rows()
+            .iter()
+            .map(|(rowframe, buttons)| {
+                buttons
+                    .iter()
+                    .map(|button| KeySpan {
+                        column: rowframe.cell_left_attach(button),
+                        width: rowframe.cell_width(button),
+                    })
+                    .collect()
+            })
+            .collect();
+        self.key_focus.borrow_mut().set_rows(rows);
+        self.show_key_focus();
+    }
+
+    fn focused_button(&self) -> Option<gtk::Button> {
+        let (row, key) = self.key_focus.borrow().focus()?;
+        self.key_rows()
+            .get(row)
+            .and_then(|(_, buttons)| buttons.get(key).cloned())
+    }
+
+    fn show_key_focus(&self) {
+        let focused = if self.key_focus_mode.get() {
+            self.focused_button()
+        } else {
+            None
+        };
+        for button in self.key_buttons() {
+            Self::set_style_class(&button, "key_focus", Some(&button) == focused.as_ref());
+        }
+    }
+
+    // Switches between the arrow keys moving the key focus and moving the
+    // text cursor. The focus is kept for when grid focus comes back.
+    pub(super) fn set_key_focus_mode(&self, enabled: bool) {
+        self.key_focus_mode.set(enabled);
+        if enabled {
+            self.update_key_focus_rows();
+            self.key_focus.borrow_mut().start();
+        }
+        self.show_key_focus();
+        self.announce(if enabled { "Key focus" } else { "Text cursor" });
+    }
+
+    pub(super) fn move_key_focus(&self, direction: FocusDirection) {
+        self.key_focus.borrow_mut().move_focus(direction);
+        self.show_key_focus();
+        // say which key has the focus now, by its accessible name
+        let name = self
+            .focused_button()
+            .and_then(|button| button.accessible())
+            .and_then(|accessible| accessible.name());
+        if let Some(name) = name {
+            self.announce(&name);
+        }
+    }
+
+    pub(super) fn press_focused_key(&self) {
+        if let Some(button) = self.focused_button() {
+            button.clicked();
+        }
+    }
+
+    // The switch was pressed: picks the highlighted row, or presses the
+    // highlighted key.
+    pub(super) fn scan_switch(&self) {
+        if !self.scanning.get() {
+            return;
+        }
+        let picked = self.scanner.borrow_mut().switch();
+        self.restart_scan_timer();
+        if let Some((row, key)) = picked {
+            let button = self
+                .key_rows()
+                .get(row)
+                .and_then(|(_, buttons)| buttons.get(key).cloned());
+            if let Some(button) = button {
+                // as if it was clicked, so it's announced and undone the same
+                button.clicked();
+            }
+        }
+    }
+
+    pub(super) fn open(&self, on_close: DialogCallback) {
+        *self.close_action.borrow_mut() = Some(on_close);
+        self.modifiers.borrow_mut().reset();
+        self.obj().show();
+        self.prompt.show();
+        self.screen.show();
+        self.apply_modifiers();
+    }
+
+    fn close(&self, result: DialogResult) {
+        let obj = self.obj();
+        obj.hide();
+        let signal = match result {
+            DialogResult::Ok(_) => "activate",
+            DialogResult::Cancelled => "cancelled",
+        };
+        obj.emit_by_name::<()>(signal, &[]);
+        let callback = self.close_action.borrow_mut().take();
+        if let Some(callback) = callback {
+            // the key is still being handled, and whoever delivered it may
+            // have state borrowed, so let the callback run once that's all done
+            glib::idle_add_local_once(move || callback(result));
+        }
+    }
+    fn key_buttons(&self) -> Vec<gtk::Button> {
+        // all buttons of all layers of the active layout
+        let mut buttons: Vec<gtk::Button> = vec![];
+        for layer in self.keys_layers.borrow().iter() {
+            for row in layer.children() {
+                if let Some(rowframe) = row.downcast_ref::<gtk::Grid>() {
+                    for key in rowframe.children() {
+                        if let Ok(button) = key.downcast::<gtk::Button>() {
+                            buttons.push(button);
+                        }
+                    }
+                }
+            }
+        }
+        buttons
+    }
+
+    fn set_style_class(widget: &impl IsA<gtk::Widget>, class: &str, enabled: bool) {
+        let style_context = widget.style_context();
+        if enabled {
+            style_context.add_class(class);
+        } else {
+            style_context.remove_class(class);
+        }
+    }
+
+    fn apply_modifiers(&self) {
+        // Shows the layer that goes with the modifier state, and marks the
+        // modifier keys so the state is visible.
+        let layer = {
+            let active_layout: usize = self.active_layout.get();
+            let layouts = self.layouts.borrow();
+            let layout = match layouts.get(active_layout) {
+                Some(layout) => layout,
+                None => return,
+            };
+            let mut modifiers = self.modifiers.borrow_mut();
+            // a named layer of the previous layout that this one doesn't have
+            if let Some(named) = modifiers.layer().map(str::to_string) {
+                if layout.layer_index(&named).is_none() {
+                    modifiers.layer_tapped(&named);
+                }
+            }
+            // Layouts are checked to have the layers their modifier keys
+            // pick, so this only falls back for one without modifier keys,
+            // like a keypad.
+            layout.layer_index(modifiers.layer_name()).unwrap_or(0)
+        };
+        self.active_key_layer.set(layer);
+        let (shift, symbols, named_layer) = {
+            let modifiers = self.modifiers.borrow();
+            (
+                modifiers.shift(),
+                modifiers.symbols(),
+                modifiers.layer().map(str::to_string),
+            )
+        };
+        for button in self.key_buttons() {
+            let name = button.widget_name();
+            if name == ID_SHIFT {
+                Self::set_style_class(&button, "shift_oneshot", shift == ShiftState::OneShot);
+                Self::set_style_class(&button, "shift_locked", shift == ShiftState::Locked);
+            } else if name == ID_SYMBOLS {
+                Self::set_style_class(&button, "symbols_active", symbols);
+            } else if let Some(layer) = name.strip_prefix(ID_LAYER_PREFIX) {
+                Self::set_style_class(
+                    &button,
+                    "layer_active",
+                    named_layer.as_deref() == Some(layer),
+                );
+            }
+        }
+        self.show_active_key_layer();
+    }
+
+    fn commit_text(&self, text: &str) {
+        // composed characters may fall outside what the caller accepts
+        if !self.accept.borrow().is_empty()
+            && !text.chars().all(|c| self.accept.borrow().contains(c))
+        {
+            return;
+        }
+        self.edit(|model| model.type_text(text));
+        self.character_typed();
+    }
+
+    fn update_compose_keys(&self) {
+        // dead and compose keys stay marked until the sequence completes
+        let pending = self.composer.borrow().is_pending();
+        for button in self.key_buttons() {
+            let name = button.widget_name();
+            if name == ID_DEAD || name == ID_COMPOSE {
+                Self::set_style_class(&button, "compose_pending", pending);
+            }
+        }
+    }
+
+    fn character_typed(&self) {
+        // a one-shot shift only lasts for a single character
+        let mut modifiers = self.modifiers.borrow_mut();
+        if modifiers.shift() == ShiftState::OneShot {
+            modifiers.character_typed();
+            drop(modifiers);
+            self.apply_modifiers();
+        }
+    }
+
+    fn activate_layout(&self, index: usize) {
+        // Swaps the key layers for those of another layout. The prompt,
+        // input and cursor position are left alone.
+        if index >= self.layouts.borrow().len() {
+            // not configured yet
+            return;
+        }
+        self.close_alternates();
+        let new_layers = Self::_create_key_layers(
+            &self.obj().downgrade(),
+            &self.layouts.borrow()[index],
+            self.orientation.get(),
+            &self.accept.borrow(),
+            self.layouts.borrow().len() > 1,
+        );
+        {
+            let mut keys_layers = self.keys_layers.borrow_mut();
+            for layer in keys_layers.iter() {
+                self.obj().remove(layer);
+            }
+            for layer in &new_layers {
+                self.obj().pack_start(layer, true, true, 0);
+            }
+            *keys_layers = new_layers;
+        }
+        self.active_layout.set(index);
+        self.apply_modifiers();
+        self.apply_font_scale();
+    }
+
+    // Fonts follow the size of the keyboard; the CSS sizes are for the
+    // reference panel, turned to match the orientation.
+    fn font_scale_for(width: i32, height: i32, orientation: LayoutOrientation) -> f64 {
+        let (reference_width, reference_height) = match orientation {
+            LayoutOrientation::Landscape => (SCREEN_WIDTH, SCREEN_HEIGHT),
+            LayoutOrientation::Portrait => (SCREEN_HEIGHT, SCREEN_WIDTH),
+        };
+        let scale =
+            (width as f64 / reference_width as f64).min(height as f64 / reference_height as f64);
+        scale.max(MIN_FONT_SCALE)
+    }
+
+    fn schedule_relayout(&self, width: i32, height: i32) {
+        let orientation = LayoutOrientation::for_size(width, height);
+        let scale = Self::font_scale_for(width, height, orientation);
+        let changed =
+            orientation != self.orientation.get() || (scale - self.font_scale.get()).abs() > 0.01;
+        if !changed || self.relayout_pending.get() {
+            return;
+        }
+        // changing the keys now would resize them in the middle of
+        // allocating them, so it waits for the allocation to finish
+        self.relayout_pending.set(true);
+        let this = self.obj().downgrade();
+        glib::idle_add_local_once(move || {
+            if let Some(virtual_keyboard) = this.upgrade() {
+                virtual_keyboard.imp().relayout();
+            }
+        });
+    }
+
+    fn relayout(&self) {
+        self.relayout_pending.set(false);
+        let obj = self.obj();
+        let (width, height) = (obj.allocated_width(), obj.allocated_height());
+        let orientation = LayoutOrientation::for_size(width, height);
+        self.font_scale
+            .set(Self::font_scale_for(width, height, orientation));
+        if orientation != self.orientation.get() {
+            // the keys are rebuilt, and get the new font scale as well
+            self.orientation.set(orientation);
+            self.activate_layout(self.active_layout.get());
+        } else {
+            self.apply_font_scale();
+        }
+    }
+
+    fn scaled_font(&self) -> Option<gtk::pango::AttrList> {
+        let scale = self.font_scale.get();
+        if scale <= 0.0 {
+            // not allocated yet
+            return None;
+        }
+        let attributes = gtk::pango::AttrList::new();
+        attributes.insert(gtk::pango::AttrFloat::new_scale(scale));
+        Some(attributes)
+    }
+
+    fn apply_font_scale(&self) {
+        let attributes = match self.scaled_font() {
+            Some(attributes) => attributes,
+            None => return,
+        };
+        self.prompt.set_attributes(Some(&attributes));
+        self.screen.set_attributes(Some(&attributes));
+        for button in self.key_buttons() {
+            if let Some(label) = button
+                .child()
+                .and_then(|child| child.downcast::<Label>().ok())
+            {
+                label.set_attributes(Some(&attributes));
+            }
+        }
+    }
+
+    fn next_layout(&self) {
+        let active_layout: usize = self.active_layout.get();
+        let new_layout = (active_layout + 1) % self.layouts.borrow().len();
+        if new_layout != active_layout {
+            self.activate_layout(new_layout);
+        }
+    }
+
+    pub(super) fn layout_name(&self) -> String {
+        let active_layout: usize = self.active_layout.get();
+        self.layouts
+            .borrow()
+            .get(active_layout)
+            .map(|layout| layout.name.clone())
+            .unwrap_or_default()
+    }
+
+    pub(super) fn set_layout(&self, name: &str) -> Result<(), LayoutError> {
+        let index = self
+            .layouts
+            .borrow()
+            .iter()
+            .position(|layout| layout.name == name)
+            .ok_or_else(|| LayoutError::UnknownLayout(name.to_string()))?;
+        if index != self.active_layout.get() {
+            self.activate_layout(index);
+        }
+        Ok(())
+    }
+
+    fn button_label_text(button: &gtk::Button) -> String {
+        let child = button.child();
+        if let Some(widget) = child {
+            // {
+            let labelwidget = widget.downcast_ref::<Label>();
+            if let Some(label) = labelwidget {
+                return label.text().to_string();
+            }
+        }
+        return "".to_string();
+    }
+
+    // Handles a key and announces what it did: the edit, or else the new
+    // layer or layout.
+    pub(super) fn handle_key(&self, button_label: &str, special_button_name: &str) {
+        let model = self.model.borrow().clone();
+        let layer = self.layer_announcement();
+        let layout = self.active_layout.get();
+        self.handle_key_unannounced(button_label, special_button_name);
+        let announcement = match special_button_name {
+            // the keyboard is gone; whoever opened it takes over
+            ID_ENTER | ID_CANCEL => None,
+            _ => accessibility::edit_announcement(
+                &model,
+                &self.model.borrow(),
+                self.purpose.get().is_masked(),
+            ),
+        };
+        let announcement = announcement
+            .or_else(|| Some(self.layer_announcement()).filter(|now| *now != layer))
+            .or_else(|| {
+                (self.active_layout.get() != layout)
+                    .then(|| accessibility::layout_announcement(&self.layout_name()))
+            });
+        if let Some(announcement) = announcement {
+            self.announce(&announcement);
+        }
+    }
+
+    fn handle_key_unannounced(&self, button_label: &str, special_button_name: &str) {
+        if special_button_name == ID_UNDO {
+            self.undo();
+            return;
+        }
+        if special_button_name == ID_REDO {
+            self.redo();
+            return;
+        }
+        if special_button_name == ID_ENTER || special_button_name == ID_CANCEL {
+            // closing the keyboard is not an edit to undo
+            self.dispatch_key(button_label, special_button_name);
+            return;
+        }
+        // typing coalesces into one undo step, unless it replaces a selection
+        let typing =
+            ["", ID_DEAD, ID_COMPOSE].contains(&special_button_name) && !self.has_selection();
+        let before = self.snapshot();
+        self.dispatch_key(button_label, special_button_name);
+        let kind = if typing {
+            EditKind::Insert
+        } else {
+            EditKind::Other
+        };
+        self.record_edit(before, kind);
+    }
+
+    fn movement(special_button_name: &str) -> Option<(Movement, bool)> {
+        // the cursor movement for a key, and whether it extends the selection
+        let movement = match special_button_name {
+            ID_LEFT => (Movement::Left, false),
+            ID_RIGHT => (Movement::Right, false),
+            ID_WORD_LEFT => (Movement::WordLeft, false),
+            ID_WORD_RIGHT => (Movement::WordRight, false),
+            ID_HOME => (Movement::Home, false),
+            ID_END => (Movement::End, false),
+            ID_SELECT_LEFT => (Movement::Left, true),
+            ID_SELECT_RIGHT => (Movement::Right, true),
+            ID_SELECT_HOME => (Movement::Home, true),
+            ID_SELECT_END => (Movement::End, true),
+            _ => return None,
+        };
+        Some(movement)
+    }
+
+    fn dispatch_key(&self, button_label: &str, special_button_name: &str) {
+        if special_button_name == ID_DISABLED {
+            return;
+        }
+        let composing = self.composer.borrow().is_pending();
+        if composing
+            && ![ID_DEAD, ID_COMPOSE, ID_SHIFT, ID_SYMBOLS, ID_LAYOUT, ""]
+                .contains(&special_button_name)
+            && !special_button_name.starts_with(ID_LAYER_PREFIX)
+        {
+            // editing keys abandon an unfinished dead key or compose sequence
+            self.composer.borrow_mut().reset();
+            self.update_compose_keys();
+            if special_button_name == ID_BACKSPACE {
+                return;
+            }
+        }
+        if special_button_name == ID_BACKSPACE {
+            self.edit(TextModel::backspace);
+            return;
+        }
+
+        if special_button_name == ""
+            || special_button_name == ID_DEAD
+            || special_button_name == ID_COMPOSE
+        {
+            let compose_input = if special_button_name == ID_DEAD {
+                match button_label.chars().next() {
+                    Some(accent) => ComposeInput::Dead(accent),
+                    None => return,
+                }
+            } else if special_button_name == ID_COMPOSE {
+                ComposeInput::Compose
+            } else {
+                ComposeInput::Text(button_label)
+            };
+            let output = self.composer.borrow_mut().feed(compose_input);
+            self.update_compose_keys();
+            if let ComposeOutput::Commit(text) = output {
+                self.commit_text(&text);
+            }
+            return;
+        }
+        if special_button_name == ID_SHIFT {
+            self.modifiers
+                .borrow_mut()
+                .shift_tapped(std::time::Instant::now());
+            self.apply_modifiers();
+            return;
+        }
+        if special_button_name == ID_SYMBOLS {
+            self.modifiers.borrow_mut().symbols_tapped();
+            self.apply_modifiers();
+            return;
+        }
+        if let Some(layer) = special_button_name.strip_prefix(ID_LAYER_PREFIX) {
+            self.modifiers.borrow_mut().layer_tapped(layer);
+            self.apply_modifiers();
+            return;
+        }
+        if special_button_name == ID_LAYOUT {
+            self.next_layout();
+            return;
+        }
+        if special_button_name == ID_SELECT_ALL {
+            self.edit(TextModel::select_all);
+            return;
+        }
+        if special_button_name == ID_COPY {
+            self.copy_selection();
+            return;
+        }
+        if special_button_name == ID_CUT {
+            self.cut_selection();
+            return;
+        }
+        if special_button_name == ID_PASTE {
+            self.paste();
+            return;
+        }
+        if let Some((movement, selecting)) = Self::movement(special_button_name) {
+            self.edit(|model| model.move_cursor(movement, selecting));
+            return;
+        }
+        if special_button_name == ID_INSERT {
+            self.edit(TextModel::toggle_insert_mode);
+            return;
+        }
+        if special_button_name == ID_DELETE {
+            self.edit(TextModel::delete);
+            return;
+        }
+        if special_button_name == ID_DELETE_WORD_BACK {
+            self.edit(TextModel::delete_word_back);
+            return;
+        }
+        if special_button_name == ID_DELETE_WORD_FORWARD {
+            self.edit(TextModel::delete_word_forward);
+            return;
+        }
+        if special_button_name == ID_CLEAR {
+            self.edit(TextModel::clear);
+            return;
+        }
+        if special_button_name == ID_ENTER {
+            self.close(DialogResult::Ok(self.text()));
+            return;
+        }
+        if special_button_name == ID_CANCEL {
+            self.close(DialogResult::Cancelled);
+            return;
+        }
+    }
+
+    fn long_press_start(&self, button: &gtk::Button, alternates: &[String]) {
+        self.close_alternates();
+        let this = self.obj().downgrade();
+        let timer = glib::timeout_add_local_once(LONG_PRESS_DELAY, move || {
+            if let Some(virtual_keyboard) = this.upgrade() {
+                virtual_keyboard.imp().open_alternates();
+            }
+        });
+        *self.long_press.borrow_mut() = Some(LongPress {
+            button: button.clone(),
+            alternates: alternates.to_vec(),
+            timer: Some(timer),
+            popover: None,
+            alternate_buttons: vec![],
+        });
+    }
+
+    fn long_press_motion(&self, button: &gtk::Button, event: &gdk::EventMotion) {
+        // highlight the alternate under the finger while the key is held
+        let (x, y) = event.position();
+        let long_press = self.long_press.borrow();
+        if let Some(pressed) = long_press.as_ref() {
+            for alternate in &pressed.alternate_buttons {
+                let selected = Self::pointer_over(button, alternate, x, y);
+                Self::set_style_class(alternate, "alternate_selected", selected);
+            }
+        }
+    }
+
+    fn long_press_release(&self, button: &gtk::Button, event: &gdk::EventButton) {
+        let (x, y) = event.position();
+        let chosen = {
+            let mut long_press = self.long_press.borrow_mut();
+            let short_press = match long_press.as_mut() {
+                None => return,
+                Some(pressed) => match pressed.timer.take() {
+                    Some(timer) => {
+                        timer.remove();
+                        true
+                    }
+                    None => false,
+                },
+            };
+            if short_press {
+                // released before the popover opened: an ordinary click
+                *long_press = None;
+                return;
+            }
+            long_press.as_ref().and_then(|pressed| {
+                pressed
+                    .alternate_buttons
+                    .iter()
+                    .find(|alternate| Self::pointer_over(button, alternate, x, y))
+                    .map(Self::button_label_text)
+            })
+        };
+        // GTK may still emit "clicked" for this release; the popover has
+        // taken over, so ignore it. The idle runs once the release is done.
+        self.suppress_click.set(true);
+        let this = self.obj().downgrade();
+        glib::idle_add_local_once(move || {
+            if let Some(virtual_keyboard) = this.upgrade() {
+                virtual_keyboard.imp().suppress_click.set(false);
+            }
+        });
+        // If the finger was lifted elsewhere the popover stays open, so that
+        // an alternate can still be tapped.
+        if let Some(text) = chosen {
+            self.insert_alternate(&text);
+        }
+    }
+
+    fn pointer_over(button: &gtk::Button, alternate: &gtk::Button, x: f64, y: f64) -> bool {
+        // x, y are relative to `button`, which has the pointer grab
+        match button.translate_coordinates(alternate, x as i32, y as i32) {
+            Some((ax, ay)) => {
+                ax >= 0
+                    && ay >= 0
+                    && ax < alternate.allocated_width()
+                    && ay < alternate.allocated_height()
+            }
+            None => false,
+        }
+    }
+
+    fn alternate_callback(&self, button: &gtk::Button) {
+        let text = Self::button_label_text(button);
+        self.insert_alternate(&text);
+    }
+
+    fn open_alternates(&self) {
+        let mut long_press = self.long_press.borrow_mut();
+        if let Some(pressed) = long_press.as_mut() {
+            // the timer has fired, so its source is already gone
+            pressed.timer = None;
+            let popover = gtk::Popover::new(Some(&pressed.button));
+            // not modal, so the held key keeps receiving pointer events
+            popover.set_modal(false);
+            popover.set_position(gtk::PositionType::Top);
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+            row.style_context().add_class("keyboard_button_row");
+            for alternate in &pressed.alternates {
+                let alternate_button = Button::with_label(alternate);
+                alternate_button.set_size_request(
+                    pressed.button.allocated_width(),
+                    pressed.button.allocated_height(),
+                );
+                alternate_button
+                    .style_context()
+                    .add_class("keyboard_button");
+                let label = alternate_button
+                    .child()
+                    .and_then(|child| child.downcast::<Label>().ok());
+                if let (Some(label), Some(attributes)) = (label, self.scaled_font()) {
+                    label.set_attributes(Some(&attributes));
+                }
+                let this = self.obj().downgrade();
+                alternate_button.connect_clicked(move |button| {
+                    if let Some(virtual_keyboard) = this.upgrade() {
+                        virtual_keyboard.imp().alternate_callback(button);
+                    }
+                });
+                row.pack_start(&alternate_button, false, false, 0);
+                pressed.alternate_buttons.push(alternate_button);
+            }
+            popover.add(&row);
+            row.show_all();
+            popover.popup();
+            pressed.popover = Some(popover);
+        }
+    }
+
+    fn close_alternates(&self) {
+        if let Some(pressed) = self.long_press.borrow_mut().take() {
+            if let Some(timer) = pressed.timer {
+                timer.remove();
+            }
+            if let Some(popover) = pressed.popover {
+                popover.popdown();
+                // the popover is owned by the toplevel, not by us
+                unsafe {
+                    popover.destroy();
+                }
+            }
+        }
+    }
+
+    fn insert_alternate(&self, text: &str) {
+        self.close_alternates();
+        self.composer.borrow_mut().reset();
+        self.update_compose_keys();
+        let kind = if !self.has_selection() {
+            EditKind::Insert
+        } else {
+            EditKind::Other
+        };
+        let before = self.snapshot();
+        self.commit_text(text);
+        self.record_edit(before, kind);
+    }
+
+    fn button_callback(&self, button: &gtk::Button) {
+        // handles keyboard button mouse clicks, mostly.
+        // Our button contains a label which contains the text (so that button width
+        // is kept fixed) so we need some trickery to read the button label.
+        let button_label = Self::button_label_text(&button);
+        //let button_name = button.name().unwrap();
+        if self.suppress_click.get() {
+            return;
+        }
+        self.close_alternates();
+
+        let name_property: glib::Value = button.property::<glib::Value>("name");
+        let special_button_name = if let Ok(string_value) = name_property.get::<String>() {
+            if string_value == "" {
+                "".to_string()
+            } else {
+                // special button clicked!
+                string_value
+            }
+        } else {
+            "".to_string()
+        };
+        self.handle_key(&button_label, &special_button_name);
+    }
+
+    fn _create_key_layers(
+        this: &glib::WeakRef<super::VirtualKeyboard>,
+        layout: &KeyboardLayout,
+        orientation: LayoutOrientation,
+        accept: &str,
+        can_switch_layout: bool,
+    ) -> Vec<gtk::Box> {
+        // define the button event handler
+        let this_for_click = this.clone();
+        let shared_callback = move |button: &gtk::Button| {
+            if let Some(virtual_keyboard) = this_for_click.upgrade() {
+                virtual_keyboard.imp().button_callback(button);
+            }
+        };
+        let mut keys_layers: Vec<gtk::Box> = vec![];
+        let rows = layout.rows_for(orientation);
+        // Every row is a grid of equal columns, as many as the widest row
+        // needs, so a key unit is equally wide in all rows and the keys
+        // stretch with the keyboard.
+        let columns = KeyboardLayout::columns(rows);
+        // draw the keyboard, one set of rows per layer
+        for keyset in 0..layout.layer_count() {
+            let keys_layer = gtk::Box::new(gtk::Orientation::Vertical, 3);
+            let mut rowframes: Vec<gtk::Grid> = vec![];
+            for row in rows {
+                let rowframe = gtk::Grid::builder()
+                    .name("keyrow")
+                    .column_homogeneous(true)
+                    .build();
+                let style_context = rowframe.style_context();
+                style_context.add_class("keyboard_button_row");
+                let mut column = 0;
+                for key in row {
+                    let name = &key.id;
+                    let label = key.label(keyset).to_string();
+
+                    if key.is_spacer() {
+                        let spacer_box = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+                        rowframe.attach(&spacer_box, column, 0, key.columns(), 1);
+                    } else {
+                        let button = Button::builder().name(name.clone()).build();
+                        let mut disabled = false;
+                        if accept != "" {
+                            if !accept.contains(&label) {
+                                if name == "" {
+                                    disabled = true;
+                                }
+                            }
+                        }
+                        if name == ID_LAYOUT && !can_switch_layout {
+                            disabled = true;
+                        }
+                        let button_label = Label::new(Some(&label));
+                        // a label too wide for its key mustn't widen the row
+                        button_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+                        button.add(&button_label);
+
+                        button.connect_clicked(shared_callback.clone());
+                        let alternates = key.alternates(keyset).to_vec();
+                        // glyphs like ⌫ mean nothing to a screen reader
+                        if let Some(accessible) = button.accessible() {
+                            accessible.set_name(&accessibility::key_name(name, &label));
+                            let description = if disabled {
+                                "Not available here".to_string()
+                            } else {
+                                accessibility::key_description(name, &alternates)
+                            };
+                            accessible.set_description(&description);
+                        }
+                        if !alternates.is_empty() && !disabled {
+                            // holding the key down pops up its alternates
+                            button.add_events(gdk::EventMask::BUTTON_MOTION_MASK);
+                            let this_for_press = this.clone();
+                            button.connect_button_press_event(move |button, _event| {
+                                if let Some(virtual_keyboard) = this_for_press.upgrade() {
+                                    virtual_keyboard.imp().long_press_start(button, &alternates);
+                                }
+                                Inhibit(false)
+                            });
+                            let this_for_motion = this.clone();
+                            button.connect_motion_notify_event(move |button, event| {
+                                if let Some(virtual_keyboard) = this_for_motion.upgrade() {
+                                    virtual_keyboard.imp().long_press_motion(button, event);
+                                }
+                                Inhibit(false)
+                            });
+                            let this_for_release = this.clone();
+                            button.connect_button_release_event(move |button, event| {
+                                if let Some(virtual_keyboard) = this_for_release.upgrade() {
+                                    virtual_keyboard.imp().long_press_release(button, event);
+                                }
+                                Inhibit(false)
+                            });
+                        }
+                        let style_context = button.style_context();
+                        if disabled {
+                            style_context.add_class("keyboard_button_disabled");
+                            button.set_property("name", ID_DISABLED.to_string());
+                        } else {
+                            style_context.add_class("keyboard_button");
+                            button.set_property("name", name);
+                        }
+
+                        button.set_hexpand(true);
+                        button.set_vexpand(true);
+                        rowframe.attach(&button, column, 0, key.columns(), 1);
+                    }
+                    column += key.columns();
+                }
+                if column < columns {
+                    // short rows are padded at the end
+                    let filler = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+                    rowframe.attach(&filler, column, 0, columns - column, 1);
+                }
+                rowframes.push(rowframe);
+            }
+            for bar in &rowframes {
+                keys_layer.pack_start(bar, true, true, 0);
+            }
+            keys_layer.hide();
+            keys_layers.push(keys_layer);
+        }
+        keys_layers
+    }
+}
@@ -0,0 +1,43 @@
+// Helpers shared by the widget tests.
+use gtk::prelude::*;
+use rustgtk::modules::virtual_keyboard::VirtualKeyboard;
+
+pub fn init() {
+    // see the Tests section of README.md for running these with a display
+    gtk::init().expect("GTK needs a display");
+}
+
+// runs what the main loop has waiting, e.g. the close callbacks of dialogs
+pub fn run_pending() {
+    while glib::MainContext::default().iteration(false) {}
+}
+
+// all widgets below `widget`, depth first
+pub fn descendants(widget: &gtk::Widget) -> Vec<gtk::Widget> {
+    let mut widgets = vec![];
+    if let Some(container) = widget.downcast_ref::<gtk::Container>() {
+        for child in container.children() {
+            widgets.push(child.clone());
+            widgets.extend(descendants(&child));
+        }
+    }
+    widgets
+}
+
+pub fn find(keyboard: &VirtualKeyboard, name: &str) -> gtk::Widget {
+    descendants(keyboard.upcast_ref())
+        .into_iter()
+        .find(|widget| widget.widget_name() == name)
+        .unwrap()
+}
+
+// a key press as a physical keyboard would send it; GTK has to be up
+pub fn synthesized_key_event(keyval: gdk::keys::Key, state: gdk::ModifierType) -> gdk::EventKey {
+    let mut event = gdk::Event::new(gdk::EventType::KeyPress)
+        .downcast::<gdk::EventKey>()
+        .unwrap();
+    let raw: &mut gdk_sys::GdkEventKey = event.as_mut();
+    raw.keyval = *keyval;
+    raw.state = state.bits();
+    event
+}
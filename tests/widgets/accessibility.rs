@@ -0,0 +1,30 @@
+// Widget tests for what the virtual keyboard tells assistive technology.
+use crate::common::find;
+use gtk::atk;
+use gtk::prelude::*;
+use rustgtk::modules::keyboard_layout::InputPurpose;
+use rustgtk::modules::virtual_keyboard::{VirtualKeyboard, ID_BACKSPACE, ID_SHIFT};
+
+pub fn test_accessible_keys() {
+    let keyboard = VirtualKeyboard::new("Name", "", InputPurpose::FreeForm);
+
+    // keys are named for what they do, not for their glyph
+    let backspace = find(&keyboard, ID_BACKSPACE).accessible().unwrap();
+    assert_eq!(backspace.name().unwrap(), "Backspace");
+    assert_eq!(
+        backspace.description().unwrap(),
+        "Deletes the character before the cursor"
+    );
+    let shift = find(&keyboard, ID_SHIFT).accessible().unwrap();
+    assert_eq!(shift.name().unwrap(), "Shift");
+
+    // the input is an entry named after the prompt, which knows its cursor
+    let screen = find(&keyboard, "screen").accessible().unwrap();
+    assert_eq!(screen.role(), atk::Role::Entry);
+    assert_eq!(screen.name().unwrap(), "Name");
+    keyboard.handle_key("a", "");
+    keyboard.handle_key("b", "");
+    assert_eq!(screen.description().unwrap(), "Cursor at 2 of 2");
+    keyboard.set_prompt_text("Surname");
+    assert_eq!(screen.name().unwrap(), "Surname");
+}
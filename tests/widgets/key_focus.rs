@@ -0,0 +1,33 @@
+// Widget tests for D-pad and rotary encoder key focus.
+use rustgtk::modules::key_focus::FocusDirection;
+use rustgtk::modules::keyboard_layout::InputPurpose;
+use rustgtk::modules::virtual_keyboard::{VirtualKeyboard, ID_LEFT};
+
+pub fn test_key_focus() {
+    let keyboard = VirtualKeyboard::new("Name", "", InputPurpose::FreeForm);
+    assert!(!keyboard.is_key_focus_mode());
+    keyboard.set_key_focus_mode(true);
+    assert!(keyboard.is_key_focus_mode());
+    // the first key of the first row; the spacer before it isn't one
+    assert_eq!(keyboard.focused_key(), Some((0, 0)));
+    keyboard.press_focused_key();
+    assert_eq!(keyboard.text(), "q");
+    keyboard.move_key_focus(FocusDirection::Right);
+    keyboard.press_focused_key();
+    assert_eq!(keyboard.text(), "qw");
+
+    // left from the first key wraps round to the wide backspace key
+    keyboard.move_key_focus(FocusDirection::Left);
+    keyboard.move_key_focus(FocusDirection::Left);
+    keyboard.press_focused_key();
+    assert_eq!(keyboard.text(), "q");
+    keyboard.move_key_focus(FocusDirection::Down);
+    assert_eq!(keyboard.focused_key().map(|(row, _)| row), Some(1));
+
+    // back in text cursor mode the focus waits where it was
+    keyboard.set_key_focus_mode(false);
+    keyboard.handle_key("", ID_LEFT);
+    assert_eq!(keyboard.cursor_position(), 0);
+    keyboard.set_key_focus_mode(true);
+    assert_eq!(keyboard.focused_key().map(|(row, _)| row), Some(1));
+}
@@ -0,0 +1,24 @@
+// Widget tests for signal handlers owned by a component.
+use gtk::prelude::*;
+use rustgtk::modules::lifecycle::Owned;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn test_owned_handlers() {
+    let label = gtk::Label::new(Some("component"));
+
+    // handlers owned by the component stop when it releases them
+    let owned = Owned::new();
+    let notified = Rc::new(RefCell::new(0));
+    let notified_for_handler = Rc::clone(&notified);
+    owned.handler(
+        &label,
+        label.connect_notify_local(Some("label"), move |_, _| {
+            *notified_for_handler.borrow_mut() += 1;
+        }),
+    );
+    label.set_text("one");
+    owned.release();
+    label.set_text("two");
+    assert_eq!(*notified.borrow(), 1);
+}
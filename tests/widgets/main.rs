@@ -0,0 +1,31 @@
+// Widget tests, which need a display. GTK only works on the thread that
+// initialised it, and every #[test] runs on a thread of its own, so they
+// all run from the one test below.
+#[path = "../common/mod.rs"]
+mod common;
+
+mod accessibility;
+mod key_focus;
+mod lifecycle;
+mod navigation;
+mod physical_keyboard;
+mod reentrant_dialog;
+mod scanning;
+mod theme;
+mod virtual_keyboard;
+
+#[test]
+#[ignore = "needs a display, see the Tests section of README.md"]
+fn test_widgets() {
+    common::init();
+    virtual_keyboard::test_properties_and_signals();
+    physical_keyboard::test_physical_keyboard();
+    accessibility::test_accessible_keys();
+    scanning::test_scanning();
+    key_focus::test_key_focus();
+    lifecycle::test_owned_handlers();
+    navigation::test_push_and_pop();
+    reentrant_dialog::test_reentrant_dialogs();
+    // last, as it switches the theme of every keyboard
+    theme::test_themes();
+}
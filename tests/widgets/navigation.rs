@@ -0,0 +1,98 @@
+// Widget tests for the navigator.
+use gtk::prelude::*;
+use rustgtk::modules::navigation::{NavigationError, Navigator, Screen};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+struct LoggingScreen {
+    widget: gtk::Label,
+    name: &'static str,
+    log: Rc<RefCell<Vec<String>>>,
+}
+
+impl Screen for LoggingScreen {
+    fn widget(&self) -> gtk::Widget {
+        self.widget.clone().upcast()
+    }
+    fn entered(&self) {
+        self.log.borrow_mut().push(format!("entered {}", self.name));
+    }
+    fn left(&self) {
+        self.log.borrow_mut().push(format!("left {}", self.name));
+    }
+}
+
+fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    let mut cx = Context::from_waker(std::task::Waker::noop());
+    Pin::new(future).poll(&mut cx)
+}
+
+pub fn test_push_and_pop() {
+    let log = Rc::new(RefCell::new(vec![]));
+    let navigator = Navigator::new();
+    for name in ["home", "settings", "keyboard"] {
+        navigator.register(
+            name,
+            LoggingScreen {
+                widget: gtk::Label::new(Some(name)),
+                name,
+                log: Rc::clone(&log),
+            },
+        );
+    }
+    assert_eq!(
+        navigator.push("nowhere"),
+        Err(NavigationError::UnknownScreen("nowhere".to_string()))
+    );
+    assert_eq!(navigator.current(), None);
+
+    navigator.push("home").unwrap();
+    navigator.push("settings").unwrap();
+    assert_eq!(navigator.current().as_deref(), Some("settings"));
+    assert_eq!(
+        navigator.widget().visible_child_name().as_deref(),
+        Some("settings")
+    );
+    assert_eq!(navigator.depth(), 2);
+
+    // a result goes back to the screen that asked for it
+    let mut answer = navigator.push_for_result::<String>("keyboard");
+    assert_eq!(poll_once(&mut answer), Poll::Pending);
+    navigator.pop_with("hello".to_string());
+    assert_eq!(
+        poll_once(&mut answer),
+        Poll::Ready(Some("hello".to_string()))
+    );
+    assert_eq!(navigator.current().as_deref(), Some("settings"));
+
+    // popping without a result, or with one of another type, gives None
+    let mut answer = navigator.push_for_result::<String>("keyboard");
+    navigator.pop();
+    assert_eq!(poll_once(&mut answer), Poll::Ready(None));
+    let mut answer = navigator.push_for_result::<String>("keyboard");
+    navigator.pop_with(42u32);
+    assert_eq!(poll_once(&mut answer), Poll::Ready(None));
+    let mut answer = navigator.push_for_result::<String>("nowhere");
+    assert_eq!(poll_once(&mut answer), Poll::Ready(None));
+
+    // the root stays put
+    navigator.pop();
+    navigator.pop();
+    assert_eq!(navigator.current().as_deref(), Some("home"));
+    assert_eq!(navigator.depth(), 1);
+
+    assert_eq!(
+        log.borrow()[..5],
+        [
+            "entered home",
+            "left home",
+            "entered settings",
+            "left settings",
+            "entered keyboard",
+        ]
+    );
+    assert_eq!(log.borrow().last().unwrap(), "entered home");
+}
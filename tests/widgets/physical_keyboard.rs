@@ -0,0 +1,49 @@
+// Widget tests for a physical keyboard typing into the virtual keyboard,
+// with synthesized key events going through physical_keyboard_handler.
+use crate::common::synthesized_key_event;
+use gdk::keys::constants as keys;
+use gtk::prelude::*;
+use rustgtk::modules::keyboard_layout::InputPurpose;
+use rustgtk::modules::virtual_keyboard::{physical_keyboard_handler, VirtualKeyboard};
+
+pub fn test_physical_keyboard() {
+    let none = gdk::ModifierType::empty();
+    let shift = gdk::ModifierType::SHIFT_MASK;
+    let control = gdk::ModifierType::CONTROL_MASK;
+    // key presses sent as a window's key-press-event would be
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    let keyboard = VirtualKeyboard::new("Name", "", InputPurpose::FreeForm);
+    window.add(&keyboard);
+    window.show_all();
+    keyboard.open(|_| {});
+    let press = |keyval: gdk::keys::Key, state: gdk::ModifierType| {
+        let event = synthesized_key_event(keyval, state);
+        physical_keyboard_handler(&keyboard, &[window.to_value(), (*event).to_value()])
+            .expect("key-press-event needs a return value")
+            .get::<bool>()
+            .unwrap()
+    };
+    assert!(press(keys::h, none));
+    assert!(press(keys::I, shift));
+    assert!(press(keys::EuroSign, none));
+    assert_eq!(keyboard.text(), "hI€");
+    assert!(press(keys::BackSpace, none));
+    assert_eq!(keyboard.text(), "hI");
+    assert!(press(keys::dead_acute, none));
+    assert!(press(keys::e, none));
+    assert_eq!(keyboard.text(), "hIé");
+    // shortcuts and keys without a meaning here go on to the application
+    assert!(!press(keys::s, control));
+    assert!(!press(keys::F1, none));
+    assert_eq!(keyboard.text(), "hIé");
+
+    // while the keyboard is hidden, keys belong to whatever is showing
+    keyboard.hide();
+    assert!(!press(keys::a, none));
+    assert!(!press(keys::BackSpace, none));
+    assert_eq!(keyboard.text(), "hIé");
+    keyboard.show();
+    assert!(press(keys::a, none));
+    assert_eq!(keyboard.text(), "hIéa");
+    unsafe { window.destroy() };
+}
@@ -0,0 +1,72 @@
+// Opening dialogs from inside the callbacks of other dialogs, the way the
+// demo application does.
+use crate::common;
+use gtk::prelude::*;
+use rustgtk::modules::home_screen::{HomeScreen, SharedData, HOME_SCREEN, KEYBOARD_SCREEN};
+use rustgtk::modules::keyboard_layout::InputPurpose;
+use rustgtk::modules::virtual_keyboard::{DialogResult, VirtualKeyboard, ID_CANCEL, ID_ENTER};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Opens the keyboard, and opens it again from the close callback while the
+// results are still borrowed, until `reopen` runs out.
+fn open_recording(
+    keyboard: &VirtualKeyboard,
+    results: &Rc<RefCell<Vec<DialogResult>>>,
+    reopen: usize,
+) {
+    let keyboard_for_callback = keyboard.clone();
+    let results_for_callback = Rc::clone(results);
+    keyboard.open(move |result| {
+        let mut results = results_for_callback.borrow_mut();
+        results.push(result);
+        if reopen > 0 {
+            open_recording(&keyboard_for_callback, &results_for_callback, reopen - 1);
+        }
+    });
+}
+
+pub fn test_reentrant_dialogs() {
+    let shared_data = Rc::new(SharedData::new());
+    let navigator = shared_data.navigator.clone();
+    let home_screen = HomeScreen::new(Rc::clone(&shared_data));
+    let keyboard_button = home_screen
+        .widget
+        .children()
+        .into_iter()
+        .find_map(|child| child.downcast::<gtk::Button>().ok())
+        .unwrap();
+    navigator.register(HOME_SCREEN, home_screen);
+    let keyboard = VirtualKeyboard::new("Text", "", InputPurpose::FreeForm);
+    navigator.register(KEYBOARD_SCREEN, keyboard.clone());
+    navigator.push(HOME_SCREEN).unwrap();
+
+    // the keyboard reopened from its own close callback
+    let results = Rc::new(RefCell::new(vec![]));
+    open_recording(&keyboard, &results, 1);
+    keyboard.handle_key("a", "");
+    keyboard.handle_key("", ID_ENTER);
+    common::run_pending();
+    assert_eq!(*results.borrow(), [DialogResult::Ok("a".to_string())]);
+    assert!(keyboard.is_visible());
+    keyboard.handle_key("", ID_CANCEL);
+    common::run_pending();
+    assert_eq!(
+        *results.borrow(),
+        [DialogResult::Ok("a".to_string()), DialogResult::Cancelled]
+    );
+    assert!(!keyboard.is_visible());
+
+    // the demo's flow, started again straight after it finished
+    for _ in 0..2 {
+        keyboard_button.clicked();
+        common::run_pending();
+        assert_eq!(navigator.current().as_deref(), Some(KEYBOARD_SCREEN));
+        assert!(keyboard.is_visible());
+        keyboard.handle_key("b", "");
+        keyboard.handle_key("", ID_ENTER);
+        common::run_pending();
+        assert_eq!(navigator.current().as_deref(), Some(HOME_SCREEN));
+        assert_eq!(keyboard.text(), "");
+    }
+}
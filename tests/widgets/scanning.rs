@@ -0,0 +1,48 @@
+// Widget tests for switch access scanning.
+use gtk::prelude::*;
+use rustgtk::modules::keyboard_layout::InputPurpose;
+use rustgtk::modules::scanning::{Highlight, ScanMode, ScanOptions};
+use rustgtk::modules::virtual_keyboard::VirtualKeyboard;
+use std::time::Duration;
+
+pub fn test_scanning() {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    let keyboard = VirtualKeyboard::new("Name", "", InputPurpose::FreeForm);
+    window.add(&keyboard);
+    window.show_all();
+    keyboard.open(|_| {});
+    // slow enough that the timer never moves the highlight during the test
+    keyboard.set_scan_options(ScanOptions {
+        interval: Duration::from_secs(60),
+        ..ScanOptions::default()
+    });
+    assert_eq!(keyboard.scan_highlight(), Highlight::None);
+    keyboard.set_scanning(true);
+    assert!(keyboard.is_scanning());
+    assert_eq!(keyboard.scan_highlight(), Highlight::Row(0));
+
+    // the first row, its first key (the spacer isn't one), and again
+    keyboard.scan_switch();
+    assert_eq!(keyboard.scan_highlight(), Highlight::Key(0, 0));
+    keyboard.scan_switch();
+    assert_eq!(keyboard.text(), "q");
+    assert_eq!(keyboard.scan_highlight(), Highlight::Row(0));
+
+    keyboard.set_scan_options(ScanOptions {
+        mode: ScanMode::Linear,
+        interval: Duration::from_secs(60),
+        ..ScanOptions::default()
+    });
+    assert_eq!(keyboard.scan_highlight(), Highlight::Key(0, 0));
+    keyboard.scan_switch();
+    assert_eq!(keyboard.text(), "qq");
+
+    // hidden, the keyboard stops scanning until shown again
+    keyboard.hide();
+    assert_eq!(keyboard.scan_highlight(), Highlight::None);
+    keyboard.show();
+    assert_eq!(keyboard.scan_highlight(), Highlight::Key(0, 0));
+    keyboard.set_scanning(false);
+    assert_eq!(keyboard.scan_highlight(), Highlight::None);
+    unsafe { window.destroy() };
+}
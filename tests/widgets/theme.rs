@@ -0,0 +1,41 @@
+// Widget tests for themes.
+use rustgtk::modules::theme::{self, ThemeError, Themes, DARK, LIGHT};
+
+pub fn test_themes() {
+    let themes = theme::themes();
+    assert_eq!(themes.current(), LIGHT);
+    assert_eq!(themes.names(), [DARK, LIGHT]);
+    Themes::check(LIGHT, theme::LIGHT_CSS).unwrap();
+    Themes::check(DARK, theme::DARK_CSS).unwrap();
+    themes.set_theme(DARK).unwrap();
+    assert_eq!(themes.current(), DARK);
+    assert!(matches!(
+        themes.set_theme("neon"),
+        Err(ThemeError::UnknownTheme(_))
+    ));
+    assert_eq!(themes.current(), DARK);
+
+    // problems are reported with their place, and change nothing
+    let broken = ".keyboard_button { color: #000; }\n#ok { colour: green; }\n";
+    match themes.add("broken", broken) {
+        Err(ThemeError::Css { theme, errors }) => {
+            assert_eq!(theme, "broken");
+            assert_eq!(errors[0].line, 2);
+        }
+        other => panic!("expected a CSS error, got {:?}", other),
+    }
+    assert!(!themes.names().contains(&"broken".to_string()));
+
+    // themes from files, reloaded from them
+    let dir = std::env::temp_dir().join(format!("rustgtk_themes_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("contrast.css");
+    std::fs::write(&path, "#ok { color: #00ff00; }").unwrap();
+    assert_eq!(themes.add_dir(&dir).unwrap(), ["contrast"]);
+    themes.set_theme("contrast").unwrap();
+    std::fs::write(&path, "#ok { colour: #00ff00; }").unwrap();
+    assert!(matches!(themes.reload(), Err(ThemeError::Css { .. })));
+    std::fs::write(&path, "#ok { color: #ffffff; }").unwrap();
+    themes.reload().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,74 @@
+// Widget tests for the virtual keyboard.
+use crate::common;
+use gtk::prelude::*;
+use rustgtk::modules::keyboard_layout::{InputPurpose, LayoutOrientation};
+use rustgtk::modules::virtual_keyboard::{VirtualKeyboard, ID_ENTER, ID_INSERT, ID_LEFT};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn test_properties_and_signals() {
+    let keyboard = VirtualKeyboard::new("Name", "", InputPurpose::FreeForm);
+    let class = glib::Class::<glib::Object>::from_type(VirtualKeyboard::static_type()).unwrap();
+    for (name, value_type) in [
+        ("text", String::static_type()),
+        ("cursor-position", u32::static_type()),
+        ("prompt", String::static_type()),
+        ("accept", String::static_type()),
+        ("insert-mode", bool::static_type()),
+    ] {
+        let pspec = class.find_property(name).unwrap();
+        assert_eq!(pspec.value_type(), value_type, "{}", name);
+        assert!(pspec.flags().contains(glib::ParamFlags::READWRITE));
+    }
+    assert_eq!(keyboard.prompt_text(), "Name");
+
+    let events = Rc::new(RefCell::new(vec![]));
+    let log = |event: &'static str| {
+        let events = Rc::clone(&events);
+        move |_: &VirtualKeyboard| events.borrow_mut().push(event)
+    };
+    keyboard.connect_changed(log("changed"));
+    keyboard.connect_activate(log("activate"));
+    keyboard.connect_cancelled(log("cancelled"));
+    let cursor_moves = Rc::new(RefCell::new(0));
+    let cursor_moves_for_notify = Rc::clone(&cursor_moves);
+    keyboard.connect_notify_local(Some("cursor-position"), move |_, _| {
+        *cursor_moves_for_notify.borrow_mut() += 1;
+    });
+
+    keyboard.set_text("héllo");
+    assert_eq!(keyboard.text(), "héllo");
+    assert_eq!(keyboard.cursor_position(), 5);
+    keyboard.handle_key("", ID_LEFT);
+    assert_eq!(keyboard.cursor_position(), 4);
+    keyboard.set_cursor_position(0);
+    assert_eq!(*cursor_moves.borrow(), 3);
+    // overwriting by default, until Ins is pressed
+    keyboard.handle_key("j", "");
+    assert_eq!(keyboard.text(), "jéllo");
+    keyboard.handle_key("", ID_INSERT);
+    assert!(keyboard.insert_mode());
+    keyboard.handle_key("!", "");
+    assert_eq!(keyboard.property::<String>("text"), "j!éllo");
+    keyboard.handle_key("", ID_ENTER);
+    assert_eq!(
+        *events.borrow(),
+        ["changed", "changed", "changed", "activate"]
+    );
+
+    // a different set of accepted characters rebuilds the keys
+    keyboard.set_accept("0123456789");
+    assert_eq!(keyboard.accept(), "0123456789");
+
+    // the keys are rearranged once the keyboard is allocated a portrait size
+    assert_eq!(keyboard.orientation(), LayoutOrientation::Landscape);
+    keyboard.show();
+    keyboard.preferred_width();
+    keyboard.preferred_height();
+    keyboard.size_allocate(&gtk::Allocation::new(0, 0, 600, 1024));
+    common::run_pending();
+    assert_eq!(keyboard.orientation(), LayoutOrientation::Portrait);
+    keyboard.size_allocate(&gtk::Allocation::new(0, 0, 1280, 800));
+    common::run_pending();
+    assert_eq!(keyboard.orientation(), LayoutOrientation::Landscape);
+}